@@ -0,0 +1,16 @@
+#!/bin/sh
+# runs the ignored linux backend tests against gtk3-demo, on a private display and accessibility bus.
+# needs Xvfb, at-spi2-core and gtk3-demo (gtk-3-examples on debian)
+set -e
+cd "$(dirname "$0")/../src-tauri"
+LAUNCHER=${ATSPI_BUS_LAUNCHER:-/usr/libexec/at-spi-bus-launcher}
+dbus-run-session -- sh -ec "
+  Xvfb :99 -screen 0 1280x1024x24 & XVFB=\$!
+  export DISPLAY=:99 GTK_A11Y=atspi NO_AT_BRIDGE=0
+  trap 'kill \$XVFB 2>/dev/null' EXIT
+  $LAUNCHER --launch-immediately &
+  sleep 1
+  gtk3-demo &
+  sleep 2
+  cargo test --bin shortkey linux::tests -- --ignored
+"
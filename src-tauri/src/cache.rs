@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::ScanMode,
+    traits::{ElementTree, Parent},
+};
+
+///walked trees by window, shown straight away while the window is walked again
+pub struct Cache {
+    ttl: Duration,
+    entries: HashMap<String, Entry>,
+}
+
+struct Entry {
+    ///0 for all windows, which a change in any application invalidates
+    pid: i32,
+    tree: ElementTree,
+    walked: Instant,
+}
+
+impl Cache {
+    ///a zero ttl caches nothing
+    pub fn new(ttl: Duration) -> Self {
+        Cache {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    ///what was last walked for the window, unless it expired
+    pub fn get(&mut self, key: &Key) -> Option<&ElementTree> {
+        let ttl = self.ttl;
+        self.entries.retain(|_, e| e.walked.elapsed() < ttl);
+        self.entries.get(&key.id).map(|e| &e.tree)
+    }
+
+    pub fn insert(&mut self, key: Key, tree: ElementTree) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let entry = Entry {
+            pid: key.pid,
+            tree,
+            walked: Instant::now(),
+        };
+        self.entries.insert(key.id, entry);
+    }
+
+    ///the application's elements changed
+    pub fn invalidate(&mut self, pid: i32) {
+        self.entries.retain(|_, e| e.pid != pid && e.pid != 0);
+    }
+}
+
+///which window a tree is of. a moved or resized window is another one, as its elements moved too
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key {
+    id: String,
+    pid: i32,
+}
+
+impl Key {
+    ///none when there is no window to cache for
+    pub fn new(mode: ScanMode, topmost: Option<&Parent>) -> Option<Self> {
+        match (mode, topmost) {
+            (ScanMode::AllWindows, _) => Some(Key {
+                id: "all".into(),
+                pid: 0,
+            }),
+            (ScanMode::Topmost, Some(t)) => Some(Key {
+                id: format!(
+                    "{}/{}/{}/{},{},{}x{}",
+                    t.pid, t.window_id, t.title, t.x, t.y, t.width, t.height
+                ),
+                pid: t.pid,
+            }),
+            (ScanMode::Topmost, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::UiElement;
+
+    fn window(pid: i32, x: i32) -> Parent {
+        Parent {
+            pid,
+            x,
+            width: 800,
+            height: 600,
+            title: "Editor".into(),
+            ..Default::default()
+        }
+    }
+
+    fn tree(name: &str) -> ElementTree {
+        ElementTree::from_flat(vec![UiElement {
+            name: name.into(),
+            ..Default::default()
+        }])
+    }
+
+    fn key(pid: i32, x: i32) -> Key {
+        Key::new(ScanMode::Topmost, Some(&window(pid, x))).unwrap()
+    }
+
+    fn cached_name(cache: &mut Cache, key: &Key) -> Option<String> {
+        cache.get(key).map(|t| t.nodes[0].element.name.clone())
+    }
+
+    #[test]
+    fn a_window_gets_what_was_walked_for_it() {
+        let mut cache = Cache::new(Duration::from_secs(10));
+        cache.insert(key(1, 0), tree("first"));
+        cache.insert(key(2, 0), tree("second"));
+        assert_eq!(
+            cached_name(&mut cache, &key(1, 0)).as_deref(),
+            Some("first")
+        );
+        assert_eq!(
+            cached_name(&mut cache, &key(2, 0)).as_deref(),
+            Some("second")
+        );
+    }
+
+    #[test]
+    fn a_moved_window_is_another_one() {
+        let mut cache = Cache::new(Duration::from_secs(10));
+        cache.insert(key(1, 0), tree("first"));
+        assert_ne!(key(1, 0), key(1, 50));
+        assert!(cache.get(&key(1, 50)).is_none());
+    }
+
+    #[test]
+    fn entries_expire() {
+        let mut cache = Cache::new(Duration::from_millis(10));
+        cache.insert(key(1, 0), tree("first"));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(cache.get(&key(1, 0)).is_none());
+    }
+
+    #[test]
+    fn a_zero_ttl_caches_nothing() {
+        let mut cache = Cache::new(Duration::ZERO);
+        cache.insert(key(1, 0), tree("first"));
+        assert!(cache.get(&key(1, 0)).is_none());
+    }
+
+    #[test]
+    fn changes_drop_the_application_and_all_windows() {
+        let mut cache = Cache::new(Duration::from_secs(10));
+        let all = Key::new(ScanMode::AllWindows, None).unwrap();
+        cache.insert(key(1, 0), tree("first"));
+        cache.insert(key(2, 0), tree("second"));
+        cache.insert(all.clone(), tree("all"));
+        cache.invalidate(1);
+        assert!(cache.get(&key(1, 0)).is_none());
+        assert!(cache.get(&all).is_none());
+        assert!(cache.get(&key(2, 0)).is_some());
+    }
+
+    #[test]
+    fn nothing_to_cache_without_a_window() {
+        assert_eq!(Key::new(ScanMode::Topmost, None), None);
+    }
+}
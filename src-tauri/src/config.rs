@@ -0,0 +1,118 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{rules::Rule, traits::Strategy};
+
+///user settings, missing fields get their defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    ///ways of invoking an element, tried in order until one works
+    pub invoke_order: Vec<Strategy>,
+    pub scan_mode: ScanMode,
+    pub overlay: OverlayMode,
+    ///also hint the taskbar, panels and tray icons
+    pub shell_surfaces: bool,
+    pub budget: WalkBudget,
+    ///how long a window's elements are shown from the cache while it is walked again, 0 to not cache
+    pub cache_millis: u64,
+    ///which elements are hinted and walked into, before the defaults
+    pub rules: Vec<Rule>,
+    ///skip elements scrolled out of view or behind another window
+    pub hide_covered: bool,
+    ///list elements scrolled out of view in the results, they are scrolled to when chosen
+    pub list_offscreen: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            invoke_order: vec![Strategy::Native, Strategy::Pointer],
+            scan_mode: ScanMode::default(),
+            overlay: OverlayMode::default(),
+            shell_surfaces: true,
+            budget: WalkBudget::default(),
+            cache_millis: 10000,
+            rules: vec![],
+            hide_covered: true,
+            list_offscreen: false,
+        }
+    }
+}
+
+///limits on a scan, so huge trees (spreadsheets, browsers, file trees) give what was found in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalkBudget {
+    ///levels below the window
+    pub max_depth: usize,
+    ///elements walked, containers too
+    pub max_elements: usize,
+    pub max_millis: u64,
+}
+
+impl Default for WalkBudget {
+    fn default() -> Self {
+        WalkBudget {
+            max_depth: 50,
+            max_elements: 20000,
+            max_millis: 5000,
+        }
+    }
+}
+
+///which windows get hints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ScanMode {
+    ///only the window which was focused when the overlay opened
+    #[default]
+    Topmost,
+    ///every window on screen, frontmost first
+    AllWindows,
+}
+
+///which monitors the overlay covers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum OverlayMode {
+    ///the monitor with the target window on it
+    #[default]
+    TargetMonitor,
+    ///one overlay spanning every monitor
+    AllMonitors,
+}
+
+impl Config {
+    ///shortkey/config.json in the os config dir
+    pub fn default_path() -> Option<PathBuf> {
+        tauri::api::path::config_dir().map(|dir| dir.join("shortkey").join("config.json"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    ///the given or default config file, falling back to the defaults
+    pub fn load_or_default(path: Option<&Path>) -> Self {
+        let Some(path) = path.map(PathBuf::from).or_else(Config::default_path) else {
+            return Config::default();
+        };
+        if !path.exists() {
+            return Config::default();
+        }
+        match Config::load(&path) {
+            Ok(config) => {
+                println!("loaded config from {path:?}");
+                config
+            }
+            Err(e) => {
+                println!("could not load config {path:?}: {e}");
+                Config::default()
+            }
+        }
+    }
+}
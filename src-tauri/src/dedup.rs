@@ -0,0 +1,194 @@
+use std::cmp::Reverse;
+
+use crate::traits::UiElement;
+
+///how far apart edges can be for two elements to be on the same spot
+const SAME_BOUNDS: i32 = 3;
+
+///controls which only show something, so a control around them is what gets clicked
+const PASSIVE: &[&str] = &[
+    "Text",
+    "Image",
+    "Label",
+    "Icon",
+    "Static",
+    "AXStaticText",
+    "AXImage",
+];
+
+///merge elements which are one target e.g. a button with its label and icon, so they get one hint.
+///the most actionable is kept, taking the best name of the ones merged into it
+pub fn dedup(mut elements: Vec<UiElement>) -> Vec<UiElement> {
+    //which element each one is merged into
+    let mut winner: Vec<Option<usize>> = vec![None; elements.len()];
+    //ties go to the first walked, which is the outer one
+    let key = |i: usize| (rank(&elements[i]), Reverse(i));
+
+    //by left edge, so only those starting within an element need comparing
+    let mut order = (0..elements.len())
+        .filter(|&i| elements[i].width > 0 && elements[i].height > 0)
+        .collect::<Vec<_>>();
+    order.sort_by_key(|&i| elements[i].x);
+    for (n, &a) in order.iter().enumerate() {
+        let reach = elements[a].x + elements[a].width.max(SAME_BOUNDS);
+        for &b in order[n + 1..]
+            .iter()
+            .take_while(|&&b| elements[b].x <= reach)
+        {
+            if !same_target(&elements[a], &elements[b]) {
+                continue;
+            }
+            let (win, lose) = if key(a) > key(b) { (a, b) } else { (b, a) };
+            if winner[lose].is_none_or(|w| key(win) > key(w)) {
+                winner[lose] = Some(win);
+            }
+        }
+    }
+
+    //an element without a name takes the longest of those merged into it
+    let mut names: Vec<Option<String>> = vec![None; elements.len()];
+    for (lose, element) in elements.iter().enumerate() {
+        let mut win = lose;
+        while let Some(w) = winner[win] {
+            win = w;
+        }
+        let name = &element.name;
+        if win != lose && names[win].as_ref().is_none_or(|n| n.len() < name.len()) {
+            names[win] = Some(name.clone());
+        }
+    }
+    for (element, name) in elements.iter_mut().zip(names) {
+        if let (true, Some(name)) = (element.name.is_empty(), name) {
+            element.name = name;
+        }
+    }
+
+    elements
+        .into_iter()
+        .zip(winner)
+        .filter(|(_, w)| w.is_none())
+        .map(|(e, _)| e)
+        .collect()
+}
+
+fn same_target(a: &UiElement, b: &UiElement) -> bool {
+    if a.pid != b.pid || a.window_id != b.window_id {
+        return false;
+    }
+    if same_bounds(a, b) {
+        return true;
+    }
+    //something passive inside another element, clicking it clicks that element
+    (is_passive(b) && contains(a, b)) || (is_passive(a) && contains(b, a))
+}
+
+fn same_bounds(a: &UiElement, b: &UiElement) -> bool {
+    (a.x - b.x).abs() <= SAME_BOUNDS
+        && (a.y - b.y).abs() <= SAME_BOUNDS
+        && (a.x + a.width - b.x - b.width).abs() <= SAME_BOUNDS
+        && (a.y + a.height - b.y - b.height).abs() <= SAME_BOUNDS
+}
+
+fn contains(outer: &UiElement, inner: &UiElement) -> bool {
+    inner.x >= outer.x
+        && inner.y >= outer.y
+        && inner.x + inner.width <= outer.x + outer.width
+        && inner.y + inner.height <= outer.y + outer.height
+}
+
+fn is_passive(element: &UiElement) -> bool {
+    !element.editable && !element.opens_menu && PASSIVE.contains(&element.control.as_str())
+}
+
+///what we would rather click
+fn rank(element: &UiElement) -> u8 {
+    if element.editable {
+        3
+    } else if element.opens_menu {
+        2
+    } else if !is_passive(element) {
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(name: &str, control: &str, x: i32, y: i32, width: i32, height: i32) -> UiElement {
+        UiElement {
+            name: name.into(),
+            control: control.into(),
+            x,
+            y,
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    fn names(elements: &[UiElement]) -> Vec<&str> {
+        elements.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn a_button_keeps_its_label_and_icon_out() {
+        let elements = vec![
+            element("Save", "Button", 10, 10, 80, 30),
+            element("Save", "Text", 40, 15, 40, 20),
+            element("", "Image", 14, 15, 20, 20),
+        ];
+        let kept = dedup(elements);
+        assert_eq!(names(&kept), ["Save"]);
+        assert_eq!(kept[0].control, "Button");
+    }
+
+    #[test]
+    fn a_button_without_a_name_takes_its_label() {
+        let elements = vec![
+            element("", "Button", 10, 10, 80, 30),
+            element("Open", "Text", 40, 15, 40, 20),
+        ];
+        assert_eq!(names(&dedup(elements)), ["Open"]);
+    }
+
+    #[test]
+    fn stacked_elements_keep_the_most_actionable() {
+        let mut edit = element("Search", "Text", 11, 10, 200, 30);
+        edit.editable = true;
+        let elements = vec![element("Search", "Group", 10, 10, 201, 31), edit];
+        let kept = dedup(elements);
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].editable);
+    }
+
+    #[test]
+    fn neighbours_are_not_merged() {
+        let elements = vec![
+            element("One", "Button", 10, 10, 30, 30),
+            element("Two", "Button", 45, 10, 30, 30),
+            //a label next to its button, not in it
+            element("Three", "Text", 80, 10, 30, 30),
+        ];
+        assert_eq!(names(&dedup(elements)), ["One", "Two", "Three"]);
+    }
+
+    #[test]
+    fn other_windows_are_not_merged() {
+        let mut other = element("Save", "Text", 10, 10, 80, 30);
+        other.window_id = "dialog".into();
+        let elements = vec![element("Save", "Button", 10, 10, 80, 30), other];
+        assert_eq!(dedup(elements).len(), 2);
+    }
+
+    #[test]
+    fn elements_without_a_size_are_kept() {
+        let elements = vec![
+            element("Tray", "TrayIcon", 0, 0, 0, 0),
+            element("Tray", "TrayIcon", 0, 0, 0, 0),
+        ];
+        assert_eq!(dedup(elements).len(), 2);
+    }
+}
@@ -0,0 +1,80 @@
+use std::{cell::RefCell, error::Error, path::Path};
+
+use crate::{
+    snapshot::Snapshot,
+    traits::{
+        AccessError, AccessibilityCalls, Action, Batches, CoordSpace, ElementTree, Parent,
+        Prerequisite, UiElement,
+    },
+};
+
+///canned elements loaded from a json file, so we can run without a real screen
+pub struct FixtureBackend {
+    topmost: Option<Parent>,
+    tree: ElementTree,
+    invoked: RefCell<Vec<(UiElement, Action)>>,
+}
+
+impl FixtureBackend {
+    pub fn new(topmost: Option<Parent>, tree: ElementTree) -> Self {
+        Self {
+            topmost,
+            tree,
+            invoked: RefCell::new(vec![]),
+        }
+    }
+
+    ///replay a scan recorded with a snapshot
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        Self::new(snapshot.topmost, snapshot.tree)
+    }
+
+    ///file is a json list of elements, missing fields are defaulted
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let elements: Vec<UiElement> = serde_json::from_str(&text)?;
+        println!("loaded {} elements from {path:?}", elements.len());
+        Ok(Self::new(None, ElementTree::from_flat(elements)))
+    }
+
+    ///every invoke so far, in order
+    #[cfg(test)]
+    pub fn invoked(&self) -> Vec<(UiElement, Action)> {
+        self.invoked.borrow().clone()
+    }
+}
+
+impl AccessibilityCalls for FixtureBackend {
+    fn check_prerequisites(&mut self) -> Vec<Prerequisite> {
+        vec![]
+    }
+
+    fn get_elements(&mut self, batches: &mut Batches) -> Result<ElementTree, AccessError> {
+        batches.flush(&self.tree);
+        Ok(self.tree.clone())
+    }
+
+    ///snapshots from a mac are in points
+    fn coord_space(&self) -> CoordSpace {
+        self.tree
+            .nodes
+            .first()
+            .map(|n| n.element.space)
+            .unwrap_or_default()
+    }
+
+    fn invoke(&self, element: &UiElement, action: Action) -> Result<(), AccessError> {
+        if self.tree.resolve(element).is_none() {
+            return Err(AccessError::Stale(element.name.clone()));
+        }
+        println!("fixture invoke {action:?} on {element:?}");
+        self.invoked.borrow_mut().push((element.clone(), action));
+        Ok(())
+    }
+
+    fn save_topmost(&mut self) {}
+
+    fn get_topmost(&self) -> Option<Parent> {
+        self.topmost.clone()
+    }
+}
@@ -0,0 +1,1325 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use active_win_pos_rs::get_active_window;
+use atspi::{
+    proxy::{
+        accessible::AccessibleProxyBlocking,
+        action::ActionProxyBlocking,
+        bus::{BusProxyBlocking, StatusProxyBlocking},
+        component::ComponentProxyBlocking,
+        editable_text::EditableTextProxyBlocking,
+        registry::RegistryProxyBlocking,
+    },
+    CoordType, Interface, ObjectRef, Role, ScrollType, State, StateSet,
+};
+use x11rb::{
+    connection::Connection as _,
+    protocol::xproto::{AtomEnum, ConnectionExt as _, MapState},
+};
+use zbus::{
+    blocking::{fdo::DBusProxy, Connection, MessageIterator},
+    message::Type as MessageType,
+    proxy::{CacheProperties, ProxyDefault},
+    MatchRule,
+};
+
+use crate::{
+    config::{Config, ScanMode},
+    monitor::Rect,
+    rules::{AppRules, Rules},
+    traits::{
+        clean, invoke_in_order, scrolled_to, AccessError, AccessibilityCalls, Action, Batches,
+        Budget, Changes, CoordSpace, ElementTree, InputCalls, Node, Parent, Prerequisite,
+        ShellSurfaces, Strategy, UiElement,
+    },
+    wayland::Wayland,
+    xtest::XTest,
+};
+
+const REGISTRY_NAME: &str = "org.a11y.atspi.Registry";
+const REGISTRY_PATH: &str = "/org/a11y/atspi/accessible/root";
+const A11Y_BUS: &str = "a11y-bus";
+const SNI_WATCHER: &str = "org.kde.StatusNotifierWatcher";
+const SNI_ITEM: &str = "org.kde.StatusNotifierItem";
+///events after which an application's cached elements are out of date, and their signal names
+const CHANGE_EVENTS: &[(&str, &str)] = &[
+    ("object:children-changed", "ChildrenChanged"),
+    ("object:bounds-changed", "BoundsChanged"),
+];
+///desktop shells and panels, whose windows are shell surfaces rather than application windows
+const SHELL_APPS: &[&str] = &[
+    "gnome-shell",
+    "plasmashell",
+    "xfce4-panel",
+    "mate-panel",
+    "budgie-panel",
+    "lxqt-panel",
+];
+///containers whose children can be scrolled out of view
+const SCROLLABLE: &[&str] = &["ScrollPane", "Viewport"];
+///how many parents up from a changed object to look for one we walked
+const CLIMB: usize = 16;
+
+pub struct Linux {
+    topmost: Option<Parent>,
+    rules: Rules,
+    config: Config,
+    connection: Option<Connection>,
+    input: Option<Box<dyn InputCalls>>,
+    tree: ElementTree,
+    //indexed by node id, tray icons added after the walk have none
+    objects: Vec<ObjectRef>,
+    //tray icons by node id, activated over dbus
+    tray: HashMap<usize, TrayItem>,
+    //of the current scan, shared with the shell surfaces
+    budget: Budget,
+    //objects which sent change events since the last walk, filled in by forward_changes
+    changed: Arc<Mutex<HashSet<ObjectRef>>>,
+    //the application being hinted, whose changes are the only ones forwarded. none for all of them
+    watched: Arc<Mutex<Option<i32>>>,
+}
+
+///the last walk, which a refresh keeps the unchanged parts of
+struct Walked {
+    tree: ElementTree,
+    objects: Vec<ObjectRef>,
+    tray: HashMap<usize, TrayItem>,
+}
+
+impl Linux {
+    pub fn new(debug: bool, config: Config) -> Self {
+        let connection = match a11y_connection() {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                println!("could not connect to accessibility bus: {e}");
+                None
+            }
+        };
+        let input = match get_input() {
+            Ok(input) => Some(input),
+            Err(e) => {
+                println!("could not set up pointer input: {e}");
+                None
+            }
+        };
+        Linux {
+            topmost: None,
+            rules: Rules::new(&config.rules, debug).with_offscreen(config.list_offscreen),
+            budget: Budget::new(&config.budget),
+            config,
+            connection,
+            input,
+            tree: ElementTree::default(),
+            objects: Vec::new(),
+            tray: HashMap::new(),
+            changed: Arc::default(),
+            watched: Arc::default(),
+        }
+    }
+
+    fn watch_hinted(&self) {
+        *self.watched.lock().unwrap() = match self.config.scan_mode {
+            ScanMode::Topmost => self.topmost.as_ref().map(|t| t.pid),
+            ScanMode::AllWindows => None,
+        };
+    }
+
+    ///copy the node and what is inside it from the last walk, walking again the parts which changed
+    fn rewalk(
+        &mut self,
+        conn: &Connection,
+        old: &Walked,
+        changed: &HashSet<usize>,
+        id: usize,
+        parent: Option<usize>,
+    ) {
+        let node = &old.tree.nodes[id];
+        if let (true, Some(obj)) = (changed.contains(&id), old.objects.get(id)) {
+            let first = self.tree.nodes.len();
+            let e = &node.element;
+            walk(
+                conn,
+                obj,
+                parent,
+                &mut self.tree,
+                &mut self.objects,
+                &mut Batches::none(),
+                &mut self.budget,
+                &self.rules.for_app(&e.parent, e.pid, false),
+            );
+            //in the same window as before
+            for new in &mut self.tree.nodes[first..] {
+                new.element.pid = e.pid;
+                new.element.parent = e.parent.clone();
+                new.element.window_id = e.window_id.clone();
+                new.element.window = e.window.clone();
+            }
+            return;
+        }
+        let new = self.tree.add(parent, node.element.clone(), node.include);
+        //tray icons no panel shows have no object, and come last
+        if let Some(obj) = old.objects.get(id) {
+            self.objects.push(obj.clone());
+        }
+        if let Some(item) = old.tray.get(&id) {
+            self.tray.insert(new, item.clone());
+        }
+        for &child in &node.children {
+            self.rewalk(conn, old, changed, child, Some(new));
+        }
+    }
+}
+
+impl AccessibilityCalls for Linux {
+    fn get_elements(&mut self, batches: &mut Batches) -> Result<ElementTree, AccessError> {
+        let start = Instant::now();
+        println!("Starting to get elements");
+        self.changed.lock().unwrap().clear();
+        self.tree = ElementTree::default();
+        self.objects.clear();
+        self.tray.clear();
+        self.budget = Budget::new(&self.config.budget);
+        let Some(conn) = self.connection.as_ref() else {
+            return Err(AccessError::PermissionDenied);
+        };
+        let windows = match self.config.scan_mode {
+            ScanMode::Topmost => {
+                let Some(topmost) = self.topmost.as_ref() else {
+                    return Ok(ElementTree::default());
+                };
+                let Some(app) = find_application(conn, topmost.pid) else {
+                    println!("no accessible application found for pid {}", topmost.pid);
+                    return Ok(ElementTree::default());
+                };
+                active_windows(conn, &app)
+                    .into_iter()
+                    .map(|obj| Window::new(conn, obj, &topmost.name, topmost.pid))
+                    .collect()
+            }
+            ScanMode::AllWindows => visible_windows(conn),
+        };
+
+        for window in windows.iter() {
+            let first = self.tree.nodes.len();
+            walk(
+                conn,
+                &window.obj,
+                None,
+                &mut self.tree,
+                &mut self.objects,
+                batches,
+                &mut self.budget,
+                &self.rules.for_app(&window.app, window.pid, false),
+            );
+            window.tag(&mut self.tree.nodes[first..]);
+        }
+        if self.config.shell_surfaces {
+            self.add_shell_surfaces(batches)?;
+        }
+        self.tree.truncated = self.budget.truncated;
+
+        println!(
+            "Got {} elements out of {} in {}ms",
+            self.tree.nodes.iter().filter(|n| n.include).count(),
+            self.tree.nodes.len(),
+            start.elapsed().as_millis()
+        );
+        Ok(self.tree.clone())
+    }
+
+    fn invoke(&self, element: &UiElement, action: Action) -> Result<(), AccessError> {
+        let start = Instant::now();
+        let Some(conn) = self.connection.as_ref() else {
+            return Err(AccessError::PermissionDenied);
+        };
+        let Some(node) = self.tree.resolve(element) else {
+            println!(
+                "no element found for {:?} out of {} elements",
+                element,
+                self.tree.nodes.len()
+            );
+            return Err(AccessError::Stale(element.name.clone()));
+        };
+        if let Some(item) = self.tray.get(&node.id) {
+            let (x, y) = (
+                element.x + element.width / 2,
+                element.y + element.height / 2,
+            );
+            return invoke_in_order(&self.config.invoke_order, element, |strategy| {
+                match strategy {
+                    Strategy::Native => item.activate(&action, x, y),
+                    //only icons found in a panel have a position
+                    Strategy::Pointer => match self.input.as_ref() {
+                        Some(input) if element.width > 0 => input.click_element(element, &action),
+                        _ => Err(AccessError::NoAction(element.name.clone())),
+                    },
+                }
+            });
+        }
+        let obj = &self.objects[node.id];
+        if !is_alive(conn, obj) {
+            return Err(AccessError::Stale(element.name.clone()));
+        }
+        let res = invoke_in_order(&self.config.invoke_order, element, |strategy| {
+            match strategy {
+                Strategy::Native => {
+                    match invoke_element(conn, obj, &action).map_err(access_error(&element.name))? {
+                        true => Ok(()),
+                        false => Err(AccessError::NoAction(element.name.clone())),
+                    }
+                }
+                Strategy::Pointer => match self.input.as_ref() {
+                    //it may have moved since we scanned
+                    Some(input) => {
+                        input.click_element(&current_bounds(conn, obj, element), &action)
+                    }
+                    None => Err(AccessError::InjectionFailed("no pointer input".into())),
+                },
+            }
+        });
+        println!("invoked in {}ms", start.elapsed().as_millis());
+        res
+    }
+
+    fn save_topmost(&mut self) {
+        let win = get_active_window();
+        if let Ok(win) = win {
+            self.topmost = Some(Parent::from(win));
+            println!("active window: {:?} ", self.topmost);
+            self.watch_hinted();
+        } else {
+            println!("no active window");
+        }
+    }
+
+    fn get_topmost(&self) -> Option<Parent> {
+        self.topmost.clone()
+    }
+
+    fn coord_space(&self) -> CoordSpace {
+        screen_space()
+    }
+
+    fn set_scan_mode(&mut self, mode: ScanMode) {
+        self.config.scan_mode = mode;
+        self.watch_hinted();
+    }
+
+    fn watch_changes(&mut self, changes: Changes) {
+        let Some(conn) = self.connection.clone() else {
+            return;
+        };
+        let (changed, watched) = (self.changed.clone(), self.watched.clone());
+        std::thread::spawn(move || {
+            if let Err(e) = forward_changes(&conn, &changes, &changed, &watched) {
+                println!("not watching for changes, cached elements only expire: {e}");
+            }
+        });
+    }
+
+    ///walks again only the elements which changed and what is inside them, the rest is kept
+    fn refresh(&mut self) -> Result<ElementTree, AccessError> {
+        let changed = std::mem::take(&mut *self.changed.lock().unwrap());
+        let Some(conn) = self.connection.clone() else {
+            return Err(AccessError::PermissionDenied);
+        };
+        let start = Instant::now();
+        let walked: HashMap<&ObjectRef, usize> = self
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(id, obj)| (obj, id))
+            .collect();
+        let mut ids = HashSet::new();
+        for obj in changed.iter() {
+            match walked_ancestor(&conn, obj, &walked) {
+                Some(id) => ids.insert(id),
+                //windows opened or closed, which only a full walk finds
+                None => return self.get_elements(&mut Batches::none()),
+            };
+        }
+        //tray icons are matched to the panel once it is walked
+        let in_changed = |id: usize| {
+            std::iter::once(id)
+                .chain(self.tree.ancestors(id).map(|a| a.id))
+                .any(|a| ids.contains(&a))
+        };
+        if self.tray.keys().any(|&id| in_changed(id)) {
+            return self.get_elements(&mut Batches::none());
+        }
+
+        let old = Walked {
+            tree: std::mem::take(&mut self.tree),
+            objects: std::mem::take(&mut self.objects),
+            tray: std::mem::take(&mut self.tray),
+        };
+        self.budget = Budget::new(&self.config.budget);
+        for root in old.tree.nodes.iter().filter(|n| n.parent.is_none()) {
+            self.rewalk(&conn, &old, &ids, root.id, None);
+        }
+        self.tree.truncated = self.budget.truncated.or(old.tree.truncated);
+        println!(
+            "walked {} changed elements again in {}ms",
+            ids.len(),
+            start.elapsed().as_millis()
+        );
+        Ok(self.tree.clone())
+    }
+
+    fn scroll_into_view(&mut self, element: &UiElement) -> Result<UiElement, AccessError> {
+        let Some(conn) = self.connection.as_ref() else {
+            return Err(AccessError::PermissionDenied);
+        };
+        let Some(node) = self.tree.resolve(element) else {
+            return Err(AccessError::Stale(element.name.clone()));
+        };
+        let error = access_error(&element.name);
+        let component: ComponentProxyBlocking =
+            proxy(conn, &self.objects[node.id]).map_err(&error)?;
+        if !component.scroll_to(ScrollType::Anywhere).map_err(&error)? {
+            return Err(AccessError::NoAction(element.name.clone()));
+        }
+        scrolled_to(element, || {
+            let (x, y, width, height) = component.get_extents(CoordType::Screen).ok()?;
+            Some(Rect {
+                x,
+                y,
+                width,
+                height,
+            })
+        })
+    }
+
+    fn window_stack(&mut self) -> Vec<Parent> {
+        let own = std::process::id() as i32;
+        match x11_stacking() {
+            //elements are tagged with accessibility ids rather than x ids, so they are matched by title
+            Ok(stacking) => stacking
+                .into_iter()
+                .filter(|w| w.pid != own)
+                .map(|w| Parent {
+                    title: clean(&w.title),
+                    window_id: String::new(),
+                    ..w
+                })
+                .collect(),
+            Err(e) => {
+                println!("no stacking order, not checking for covered elements: {e}");
+                vec![]
+            }
+        }
+    }
+
+    fn get_open_menu(&mut self, from: &UiElement) -> Result<Option<ElementTree>, AccessError> {
+        let Some(conn) = self.connection.as_ref() else {
+            return Err(AccessError::PermissionDenied);
+        };
+        //the menu belongs to the application of the element which opened it
+        let pid = Some(from.pid).filter(|p| *p != 0);
+        let Some(pid) = pid.or(self.topmost.as_ref().map(|t| t.pid)) else {
+            return Ok(None);
+        };
+        let Some(app) = find_application(conn, pid) else {
+            return Ok(None);
+        };
+        let rules = self.rules.for_app(&from.parent, pid, true);
+        let mut budget = Budget::new(&self.config.budget);
+        let Some(menu) = find_open_menu(conn, &app, &rules, 0, &mut budget, &mut 0) else {
+            return Ok(None);
+        };
+        self.tree = ElementTree::default();
+        self.objects.clear();
+        self.tray.clear();
+        walk(
+            conn,
+            &menu,
+            None,
+            &mut self.tree,
+            &mut self.objects,
+            &mut Batches::none(),
+            &mut Budget::new(&self.config.budget),
+            &rules,
+        );
+        //only its items, not the menu itself
+        if let Some(root) = self.tree.nodes.first_mut() {
+            root.include = false;
+        }
+        let mut window = Window::new(conn, menu, &from.parent, pid);
+        window.title = "menu".into();
+        window.tag(&mut self.tree.nodes);
+        Ok(Some(self.tree.clone()))
+    }
+
+    fn check_prerequisites(&mut self) -> Vec<Prerequisite> {
+        //it may have been turned on since we started
+        if self.connection.is_none() {
+            self.connection = a11y_connection().ok();
+        }
+        let enabled = a11y_enabled().unwrap_or(false);
+        let apps = self
+            .connection
+            .as_ref()
+            .map_or(0, |conn| other_applications(conn).len());
+        vec![
+            Prerequisite::new(A11Y_BUS, "accessibility is enabled for the session", enabled)
+                .hint("org.a11y.Status IsEnabled is off, it can be turned on here")
+                .fixable(),
+            Prerequisite::new(
+                "a11y-connection",
+                "connected to the accessibility bus",
+                self.connection.is_some(),
+            )
+            .hint("install at-spi2-core, so the accessibility bus runs in the session"),
+            Prerequisite::new("toolkit-trees", "applications expose their elements", apps > 0)
+                .hint("run `gsettings set org.gnome.desktop.interface toolkit-accessibility true` and for Qt set QT_LINUX_ACCESSIBILITY_ALWAYS_ON=1, then restart the applications"),
+            Prerequisite::new("pointer-input", "mouse clicks can be sent", self.input.is_some())
+                .hint("needs the XTest extension on X11, or a wlroots compositor on Wayland"),
+        ]
+    }
+
+    fn enable_prerequisite(&mut self, id: &str) -> Result<(), AccessError> {
+        if id != A11Y_BUS {
+            return Err(AccessError::Backend(format!(
+                "{id} cannot be enabled from here"
+            )));
+        }
+        let enable = || -> zbus::Result<()> {
+            let session = Connection::session()?;
+            StatusProxyBlocking::new(&session)?.set_is_enabled(true)
+        };
+        enable().map_err(access_error(id))?;
+        if self.connection.is_none() {
+            self.connection = Some(a11y_connection().map_err(access_error(id))?);
+        }
+        Ok(())
+    }
+}
+
+impl ShellSurfaces for Linux {
+    fn add_shell_surfaces(&mut self, batches: &mut Batches) -> Result<(), AccessError> {
+        let start = Instant::now();
+        let Some(conn) = self.connection.as_ref() else {
+            return Err(AccessError::PermissionDenied);
+        };
+        let first = self.tree.nodes.len();
+        for (app, pid) in applications(conn) {
+            let Ok(acc) = proxy::<AccessibleProxyBlocking>(conn, &app) else {
+                continue;
+            };
+            let name = acc.name().unwrap_or_default();
+            if !is_shell(&name) {
+                continue;
+            }
+            for obj in acc.get_children().unwrap_or_default() {
+                let window_first = self.tree.nodes.len();
+                walk(
+                    conn,
+                    &obj,
+                    None,
+                    &mut self.tree,
+                    &mut self.objects,
+                    batches,
+                    &mut self.budget,
+                    &self.rules.for_app(&name, pid.unwrap_or_default(), false),
+                );
+                let mut window = Window::new(conn, obj, &name, pid.unwrap_or_default());
+                window.title = "panel".into();
+                window.tag(&mut self.tree.nodes[window_first..]);
+            }
+        }
+        let panels = self.tree.nodes.len() - first;
+
+        //the panel may show the icon, which gives us its position
+        let items = tray_items();
+        for item in items.iter() {
+            let shown = self.tree.nodes[first..]
+                .iter()
+                .find(|n| n.element.width > 0 && item.matches(&n.element.name))
+                .map(|n| n.id);
+            let id = match shown {
+                Some(id) => {
+                    self.tree.nodes[id].include = true;
+                    id
+                }
+                None => {
+                    let element = UiElement {
+                        id: format!("sni:{}{}", item.service, item.path),
+                        name: clean(item.name()),
+                        control: "TrayIcon".into(),
+                        parent: "tray".into(),
+                        window: "tray".into(),
+                        ..Default::default()
+                    };
+                    self.tree.add(None, element, true)
+                }
+            };
+            self.tray.insert(id, item.clone());
+        }
+        println!(
+            "got {panels} panel elements and {} tray icons in {}ms",
+            items.len(),
+            start.elapsed().as_millis()
+        );
+        Ok(())
+    }
+}
+
+///maps errors about the named element, which is stale when its application or object went away
+fn access_error(name: &str) -> impl Fn(zbus::Error) -> AccessError + '_ {
+    move |e| match &e {
+        zbus::Error::MethodError(error, _, _)
+            if error.as_str() == "org.freedesktop.DBus.Error.NoReply"
+                || error.as_str() == "org.freedesktop.DBus.Error.Timeout"
+                || error.as_str() == "org.freedesktop.DBus.Error.TimedOut" =>
+        {
+            AccessError::Timeout
+        }
+        //the application or object has gone away
+        zbus::Error::MethodError(error, _, _)
+            if error.as_str() == "org.freedesktop.DBus.Error.ServiceUnknown"
+                || error.as_str() == "org.freedesktop.DBus.Error.UnknownObject" =>
+        {
+            AccessError::Stale(name.to_string())
+        }
+        _ => AccessError::Backend(e.to_string()),
+    }
+}
+
+fn wayland_session() -> bool {
+    std::env::var("XDG_SESSION_TYPE").is_ok_and(|s| s == "wayland")
+        || std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+///the compositor lays out outputs in logical pixels, which is also what the virtual pointer moves in
+fn screen_space() -> CoordSpace {
+    match wayland_session() {
+        true => CoordSpace::Logical,
+        false => CoordSpace::Physical,
+    }
+}
+
+///XTest does not reach native wayland windows, so pick by session type
+fn get_input() -> Result<Box<dyn InputCalls>, Box<dyn std::error::Error>> {
+    if wayland_session() {
+        Ok(Box::new(Wayland::new()?))
+    } else {
+        Ok(Box::new(XTest::new()?))
+    }
+}
+
+///the session wide accessibility switch, which toolkits check before exporting their trees
+fn a11y_enabled() -> zbus::Result<bool> {
+    let session = Connection::session()?;
+    StatusProxyBlocking::new(&session)?.is_enabled()
+}
+
+///the registry lives on its own bus, whose address we get from the session bus
+fn a11y_connection() -> zbus::Result<Connection> {
+    let session = Connection::session()?;
+    let address = BusProxyBlocking::new(&session)?.get_address()?;
+    zbus::blocking::connection::Builder::address(address.as_str())?.build()
+}
+
+fn proxy<'a, P>(conn: &Connection, obj: &ObjectRef) -> zbus::Result<P>
+where
+    P: From<zbus::Proxy<'a>> + ProxyDefault,
+{
+    zbus::blocking::proxy::Builder::new(conn)
+        .destination(obj.name.to_string())?
+        .path(obj.path.to_string())?
+        .cache_properties(CacheProperties::No)
+        .build()
+}
+
+///every registered application with the process it belongs to
+fn applications(conn: &Connection) -> Vec<(ObjectRef, Option<i32>)> {
+    let (Ok(name), Ok(path)) = (REGISTRY_NAME.try_into(), REGISTRY_PATH.try_into()) else {
+        return vec![];
+    };
+    let registry = ObjectRef { name, path };
+    let (Ok(dbus), Ok(root)) = (
+        DBusProxy::new(conn),
+        proxy::<AccessibleProxyBlocking>(conn, &registry),
+    ) else {
+        return vec![];
+    };
+    root.get_children()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|app| {
+            let pid = dbus
+                .get_connection_unix_process_id(app.name.as_ref().into())
+                .ok()
+                .map(|p| p as i32);
+            (app, pid)
+        })
+        .collect()
+}
+
+///ask applications to send change events, and note which of their objects sent them.
+///only those of the watched application are noted, as the others are not hinted
+fn forward_changes(
+    conn: &Connection,
+    changes: &Changes,
+    changed: &Mutex<HashSet<ObjectRef>>,
+    watched: &Mutex<Option<i32>>,
+) -> zbus::Result<()> {
+    let registry = RegistryProxyBlocking::new(conn)?;
+    for (event, _) in CHANGE_EVENTS {
+        registry.register_event(event)?;
+    }
+    let rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface("org.a11y.atspi.Event.Object")?
+        .build();
+    let dbus = DBusProxy::new(conn)?;
+    let own = std::process::id() as i32;
+    //senders are unique names, which belong to one process
+    let mut pids: HashMap<String, Option<i32>> = HashMap::new();
+    for msg in MessageIterator::for_match_rule(rule, conn, None)? {
+        let msg = msg?;
+        let header = msg.header();
+        let (Some(member), Some(sender), Some(path)) =
+            (header.member(), header.sender(), header.path())
+        else {
+            continue;
+        };
+        if !CHANGE_EVENTS.iter().any(|(_, m)| member.as_str() == *m) {
+            continue;
+        }
+        let pid = *pids.entry(sender.to_string()).or_insert_with(|| {
+            dbus.get_connection_unix_process_id(sender.clone().into())
+                .ok()
+                .map(|p| p as i32)
+        });
+        //the overlay changes whenever it shows
+        let watched = *watched.lock().unwrap();
+        if let Some(pid) = pid.filter(|p| *p != own && watched.is_none_or(|w| w == *p)) {
+            changes.lock().unwrap().insert(pid);
+            changed.lock().unwrap().insert(ObjectRef {
+                name: sender.to_owned().into(),
+                path: path.to_owned().into(),
+            });
+        }
+    }
+    Ok(())
+}
+
+///the closest walked element the object is in, none if it is not in a walked window
+fn walked_ancestor(
+    conn: &Connection,
+    obj: &ObjectRef,
+    walked: &HashMap<&ObjectRef, usize>,
+) -> Option<usize> {
+    let mut obj = obj.clone();
+    for _ in 0..CLIMB {
+        if let Some(&id) = walked.get(&obj) {
+            return Some(id);
+        }
+        obj = proxy::<AccessibleProxyBlocking>(conn, &obj)
+            .and_then(|acc| acc.parent())
+            .ok()?;
+    }
+    None
+}
+
+///find the registered application which belongs to the process
+fn find_application(conn: &Connection, pid: i32) -> Option<ObjectRef> {
+    applications(conn)
+        .into_iter()
+        .find(|(_, p)| *p == Some(pid))
+        .map(|(app, _)| app)
+}
+
+///registered applications apart from us, as the overlay registers too
+fn other_applications(conn: &Connection) -> Vec<ObjectRef> {
+    let own = std::process::id() as i32;
+    applications(conn)
+        .into_iter()
+        .filter(|(_, p)| *p != Some(own))
+        .map(|(app, _)| app)
+        .collect()
+}
+
+///a top level window to walk, with what we tag its elements with
+struct Window {
+    obj: ObjectRef,
+    pid: i32,
+    id: String,
+    title: String,
+    app: String,
+}
+
+impl Window {
+    fn new(conn: &Connection, obj: ObjectRef, app: &str, pid: i32) -> Self {
+        let title = proxy::<AccessibleProxyBlocking>(conn, &obj)
+            .and_then(|acc| acc.name())
+            .unwrap_or_default();
+        Window {
+            id: format!("{}{}", obj.name.as_str(), obj.path.as_str()),
+            obj,
+            pid,
+            title: clean(&title),
+            app: clean(app),
+        }
+    }
+
+    fn tag(&self, nodes: &mut [Node]) {
+        for node in nodes {
+            node.element.pid = self.pid;
+            node.element.parent = self.app.clone();
+            node.element.window_id = self.id.clone();
+            node.element.window = self.title.clone();
+        }
+    }
+}
+
+fn is_shell(app: &str) -> bool {
+    SHELL_APPS.contains(&app.to_lowercase().as_str())
+}
+
+///an icon registered over StatusNotifierItem, which is how applications put icons in the tray
+#[derive(Debug, Clone)]
+struct TrayItem {
+    service: String,
+    path: String,
+    title: String,
+    app_id: String,
+}
+
+impl TrayItem {
+    fn name(&self) -> &str {
+        if self.title.is_empty() {
+            &self.app_id
+        } else {
+            &self.title
+        }
+    }
+
+    ///panels name the icon by its title, or its id when it has none
+    fn matches(&self, name: &str) -> bool {
+        !name.is_empty()
+            && [&self.title, &self.app_id]
+                .iter()
+                .any(|n| clean(n).eq_ignore_ascii_case(name))
+    }
+
+    ///the host passes where it was clicked, for the item to put its menu there
+    fn activate(&self, action: &Action, x: i32, y: i32) -> Result<(), AccessError> {
+        let method = match action {
+            Action::LeftClick => "Activate",
+            Action::MiddleClick => "SecondaryActivate",
+            Action::RightClick => "ContextMenu",
+            _ => return Err(AccessError::NoAction(self.name().to_string())),
+        };
+        let error = access_error(self.name());
+        let session = Connection::session().map_err(&error)?;
+        let item = zbus::blocking::Proxy::new(
+            &session,
+            self.service.as_str(),
+            self.path.as_str(),
+            SNI_ITEM,
+        )
+        .map_err(&error)?;
+        //some items are only a menu, and do nothing on activate
+        let method = match item.get_property::<bool>("ItemIsMenu") {
+            Ok(true) if method == "Activate" => "ContextMenu",
+            _ => method,
+        };
+        println!("tray {method} on {}", self.name());
+        item.call_method(method, &(x, y)).map_err(error)?;
+        Ok(())
+    }
+}
+
+///icons registered with the tray, none when nothing hosts a tray
+fn tray_items() -> Vec<TrayItem> {
+    let registered = Connection::session().and_then(|session| {
+        let watcher = zbus::blocking::Proxy::new(
+            &session,
+            SNI_WATCHER,
+            "/StatusNotifierWatcher",
+            SNI_WATCHER,
+        )?;
+        let items: Vec<String> = watcher.get_property("RegisteredStatusNotifierItems")?;
+        Ok((session, items))
+    });
+    let (session, registered) = match registered {
+        Ok(registered) => registered,
+        Err(e) => {
+            println!("no tray: {e}");
+            return vec![];
+        }
+    };
+    registered
+        .iter()
+        .filter_map(|entry| {
+            //either a bus name, or a bus name followed by the object path
+            let (service, path) = match entry.find('/') {
+                Some(i) => entry.split_at(i),
+                None => (entry.as_str(), "/StatusNotifierItem"),
+            };
+            let item = zbus::blocking::Proxy::new(&session, service, path, SNI_ITEM).ok()?;
+            Some(TrayItem {
+                service: service.to_string(),
+                path: path.to_string(),
+                title: item.get_property("Title").unwrap_or_default(),
+                app_id: item.get_property("Id").unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+///showing windows of every application apart from us, frontmost first where we can tell
+fn visible_windows(conn: &Connection) -> Vec<Window> {
+    let own = std::process::id() as i32;
+    let mut windows = vec![];
+    for (app, pid) in applications(conn) {
+        let Some(pid) = pid.filter(|p| *p != own) else {
+            continue;
+        };
+        let Ok(acc) = proxy::<AccessibleProxyBlocking>(conn, &app) else {
+            continue;
+        };
+        let name = acc.name().unwrap_or_default();
+        //their panels are walked as shell surfaces
+        if is_shell(&name) {
+            continue;
+        }
+        for obj in acc.get_children().unwrap_or_default() {
+            let showing = proxy::<AccessibleProxyBlocking>(conn, &obj)
+                .and_then(|w| w.get_state())
+                .is_ok_and(|s| s.contains(State::Showing) && !s.contains(State::Iconified));
+            if showing {
+                windows.push(Window::new(conn, obj, &name, pid));
+            }
+        }
+    }
+    //at-spi has no stacking order, so get it from the window manager
+    match x11_stacking() {
+        Ok(stacking) => {
+            let position = |w: &Window| {
+                stacking
+                    .iter()
+                    .position(|s| s.pid == w.pid && clean(&s.title) == w.title)
+                    .or_else(|| stacking.iter().position(|s| s.pid == w.pid))
+                    .unwrap_or(usize::MAX)
+            };
+            windows.sort_by_cached_key(position);
+        }
+        Err(e) => println!("no stacking order, using registry order: {e}"),
+    }
+    windows
+}
+
+///the mapped windows frontmost first, from the window manager
+fn x11_stacking() -> Result<Vec<Parent>, Box<dyn std::error::Error>> {
+    if wayland_session() {
+        return Err("not available on wayland".into());
+    }
+    let (conn, screen) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen].root;
+    let atom = |name: &str| -> Result<u32, Box<dyn std::error::Error>> {
+        Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+    };
+    let stacking = atom("_NET_CLIENT_LIST_STACKING")?;
+    let wm_pid = atom("_NET_WM_PID")?;
+    let wm_name = atom("_NET_WM_NAME")?;
+    let utf8 = atom("UTF8_STRING")?;
+
+    let clients = conn
+        .get_property(false, root, stacking, AtomEnum::WINDOW, 0, u32::MAX)?
+        .reply()?;
+    let clients = clients
+        .value32()
+        .ok_or("no client list")?
+        .collect::<Vec<_>>();
+    let client = |window: u32| -> Result<Option<Parent>, Box<dyn std::error::Error>> {
+        //minimized or on another workspace
+        if conn.get_window_attributes(window)?.reply()?.map_state != MapState::VIEWABLE {
+            return Ok(None);
+        }
+        let pid = conn
+            .get_property(false, window, wm_pid, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut v| v.next())
+            .unwrap_or(0);
+        let title = conn
+            .get_property(false, window, wm_name, utf8, 0, u32::MAX)?
+            .reply()?
+            .value;
+        let size = conn.get_geometry(window)?.reply()?;
+        let origin = conn.translate_coordinates(window, root, 0, 0)?.reply()?;
+        Ok(Some(Parent {
+            pid: pid as i32,
+            title: String::from_utf8_lossy(&title).to_string(),
+            window_id: window.to_string(),
+            x: origin.dst_x.into(),
+            y: origin.dst_y.into(),
+            width: size.width.into(),
+            height: size.height.into(),
+            ..Default::default()
+        }))
+    };
+    let mut windows = vec![];
+    //bottom to top
+    for window in clients.into_iter().rev() {
+        match client(window) {
+            Ok(Some(parent)) => windows.push(parent),
+            Ok(None) => {}
+            //it closed while we asked about it
+            Err(e) => println!("skipping window {window}: {e}"),
+        }
+    }
+    Ok(windows)
+}
+
+///only walk the windows of the application which are active, else all of them
+fn active_windows(conn: &Connection, app: &ObjectRef) -> Vec<ObjectRef> {
+    let Ok(acc) = proxy::<AccessibleProxyBlocking>(conn, app) else {
+        return vec![];
+    };
+    let windows = acc.get_children().unwrap_or_default();
+    let active = windows
+        .iter()
+        .filter(|w| {
+            proxy::<AccessibleProxyBlocking>(conn, w)
+                .and_then(|w| w.get_state())
+                .is_ok_and(|s| s.contains(State::Active))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if active.is_empty() {
+        windows
+    } else {
+        active
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn walk(
+    conn: &Connection,
+    obj: &ObjectRef,
+    parent: Option<usize>,
+    tree: &mut ElementTree,
+    objects: &mut Vec<ObjectRef>,
+    batches: &mut Batches,
+    budget: &mut Budget,
+    rules: &AppRules,
+) {
+    if budget.spent(tree) {
+        return;
+    }
+    let Ok(acc) = proxy::<AccessibleProxyBlocking>(conn, obj) else {
+        return;
+    };
+    let Ok(state) = acc.get_state() else {
+        return;
+    };
+    //hidden subtrees are not on screen, so dont bother descending.
+    //rows scrolled out of a list are still visible, they are listed without their children
+    let offscreen = !state.contains(State::Showing);
+    let listed = rules.offscreen
+        && state.contains(State::Visible)
+        && parent.is_some_and(|p| tree.within(p, SCROLLABLE));
+    if (offscreen && !listed) || state.contains(State::Defunct) {
+        return;
+    }
+    let role = acc.get_role().unwrap_or(Role::Invalid);
+    let mut element = into_element(conn, &acc, obj, role);
+    element.offscreen = offscreen;
+    element.editable = state.contains(State::Editable);
+    element.states = states(state);
+    //menu bar items and submenus have the menu role
+    element.opens_menu = role == Role::Menu || state.contains(State::HasPopup);
+
+    let include = match must_include(&element, rules) {
+        Ok(()) => true,
+        Err(reason) => {
+            if rules.debug {
+                println!(
+                    "excluding {} because {reason}",
+                    AccessibleDisplay(conn, obj.clone())
+                );
+            }
+            false
+        }
+    };
+    let id = tree.add(parent, element, include);
+    objects.push(obj.clone());
+    batches.walked(tree);
+
+    let has_children = || acc.child_count().is_ok_and(|c| c > 0);
+    if offscreen
+        || !budget.descend(&tree.nodes[id], has_children)
+        || !rules.descend(&tree.nodes[id].element)
+    {
+        return;
+    }
+    for child in acc.get_children().unwrap_or_default() {
+        walk(
+            conn,
+            &child,
+            Some(id),
+            tree,
+            objects,
+            batches,
+            budget,
+            rules,
+        );
+    }
+}
+
+///the states rules can match on, at-spi gives them all at once
+fn states(state: StateSet) -> Vec<String> {
+    [
+        (State::Enabled, "enabled"),
+        (State::Focusable, "focusable"),
+        (State::Focused, "focused"),
+        (State::Selected, "selected"),
+        (State::Checked, "checked"),
+        (State::Expanded, "expanded"),
+    ]
+    .into_iter()
+    .filter(|(s, _)| state.contains(*s))
+    .map(|(_, name)| name.to_string())
+    .collect()
+}
+
+fn into_element(
+    conn: &Connection,
+    acc: &AccessibleProxyBlocking,
+    obj: &ObjectRef,
+    role: Role,
+) -> UiElement {
+    let name = clean(&acc.name().unwrap_or_default());
+    //not everything has a position e.g. the application
+    let (x, y, width, height) = proxy::<ComponentProxyBlocking>(conn, obj)
+        .and_then(|c| c.get_extents(CoordType::Screen))
+        .unwrap_or_default();
+
+    UiElement {
+        id: format!("{}{}", obj.name.as_str(), obj.path.as_str()),
+        name,
+        x,
+        y,
+        width,
+        height,
+        control: format!("{role:?}"),
+        space: screen_space(),
+        ..Default::default()
+    }
+}
+
+///the innermost menu whose items are showing, closed menus keep their items hidden.
+///it runs every 50ms while a menu opens, so it gives up where a walk would
+fn find_open_menu(
+    conn: &Connection,
+    obj: &ObjectRef,
+    rules: &AppRules,
+    depth: usize,
+    budget: &mut Budget,
+    looked_at: &mut usize,
+) -> Option<ObjectRef> {
+    *looked_at += 1;
+    if depth > budget.max_depth() || budget.spent_after(*looked_at) {
+        return None;
+    }
+    let acc = proxy::<AccessibleProxyBlocking>(conn, obj).ok()?;
+    if !is_showing(&acc) {
+        return None;
+    }
+    let role = acc.get_role().unwrap_or(Role::Invalid);
+    let element = UiElement {
+        control: format!("{role:?}"),
+        ..Default::default()
+    };
+    if !rules.descend(&element) {
+        return None;
+    }
+    let children = acc.get_children().unwrap_or_default();
+    //submenus are inside their menu, so look deeper first
+    let menu = children
+        .iter()
+        .find_map(|c| find_open_menu(conn, c, rules, depth + 1, budget, looked_at));
+    if let Some(menu) = menu {
+        return Some(menu);
+    }
+    let open = matches!(role, Role::Menu | Role::PopupMenu)
+        && children
+            .iter()
+            .any(|c| proxy::<AccessibleProxyBlocking>(conn, c).is_ok_and(|c| is_showing(&c)));
+    open.then(|| obj.clone())
+}
+
+fn is_showing(acc: &AccessibleProxyBlocking) -> bool {
+    acc.get_state().is_ok_and(|s| s.contains(State::Showing))
+}
+
+///objects which are gone return errors, or are marked defunct
+fn is_alive(conn: &Connection, obj: &ObjectRef) -> bool {
+    proxy::<AccessibleProxyBlocking>(conn, obj)
+        .and_then(|acc| acc.get_state())
+        .is_ok_and(|state| !state.contains(State::Defunct))
+}
+
+fn current_bounds(conn: &Connection, obj: &ObjectRef, element: &UiElement) -> UiElement {
+    let mut element = element.clone();
+    if let Ok((x, y, width, height)) =
+        proxy::<ComponentProxyBlocking>(conn, obj).and_then(|c| c.get_extents(CoordType::Screen))
+    {
+        element.x = x;
+        element.y = y;
+        element.width = width;
+        element.height = height;
+    }
+    element
+}
+
+fn must_include(element: &UiElement, rules: &AppRules) -> Result<(), String> {
+    rules.include(element)?;
+    if !element.offscreen && (element.width <= 0 || element.height <= 0) {
+        return Err("Offscreen".into());
+    }
+    Ok(())
+}
+
+///returns false if the element has no action for this
+fn invoke_element(conn: &Connection, obj: &ObjectRef, action: &Action) -> zbus::Result<bool> {
+    let acc: AccessibleProxyBlocking = proxy(conn, obj)?;
+    println!("invoking {}", AccessibleDisplay(conn, obj.clone()));
+    let interfaces = acc.get_interfaces()?;
+    if let Action::Focus = action {
+        if !interfaces.contains(Interface::Component) {
+            println!("element can not take focus");
+            return Ok(false);
+        }
+        let component: ComponentProxyBlocking = proxy(conn, obj)?;
+        return component.grab_focus();
+    }
+    if let Action::Type(text) = action {
+        if !interfaces.contains(Interface::EditableText) {
+            println!("element does not take text");
+            return Ok(false);
+        }
+        //so the app sees the change like a user edit
+        if interfaces.contains(Interface::Component) {
+            proxy::<ComponentProxyBlocking>(conn, obj)?.grab_focus()?;
+        }
+        let editable: EditableTextProxyBlocking = proxy(conn, obj)?;
+        return editable.set_text_contents(text);
+    }
+    if !interfaces.contains(Interface::Action) {
+        println!("element has no actions");
+        return Ok(false);
+    }
+    let actions: ActionProxyBlocking = proxy(conn, obj)?;
+    let names = actions
+        .get_actions()?
+        .into_iter()
+        .map(|(name, _, _)| name.to_lowercase())
+        .collect::<Vec<_>>();
+    let wanted: &[&str] = match action {
+        Action::LeftClick => &["click", "press", "activate", "jump", "toggle", "open"],
+        Action::RightClick => &["showmenu", "show menu", "popup", "menu"],
+        //no accessible equivalent, use the pointer
+        _ => &[],
+    };
+    let index = names
+        .iter()
+        .position(|n| wanted.contains(&n.as_str()))
+        .or(match action {
+            //if nothing is named as we expect, the first action is the default one
+            Action::LeftClick if !names.is_empty() => Some(0),
+            _ => None,
+        });
+    if let Some(index) = index {
+        actions.do_action(index as i32)
+    } else {
+        println!("no action for {action:?} in {names:?}");
+        Ok(false)
+    }
+}
+
+struct AccessibleDisplay<'a>(&'a Connection, ObjectRef);
+impl Display for AccessibleDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Ok(acc) = proxy::<AccessibleProxyBlocking>(self.0, &self.1) else {
+            return write!(f, "Accessible {{ path: {} }}", self.1.path.as_str());
+        };
+        let name = acc.name().unwrap_or_default();
+        let role = acc.get_role_name().unwrap_or_default();
+        let extents = proxy::<ComponentProxyBlocking>(self.0, &self.1)
+            .and_then(|c| c.get_extents(CoordType::Screen))
+            .unwrap_or_default();
+
+        write!(
+            f,
+            "Accessible {{  x: {}, y: {}, width: {}, height: {}, role: {}, name:'{name}' path:{}}}",
+            extents.0,
+            extents.1,
+            extents.2,
+            extents.3,
+            role,
+            self.1.path.as_str()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window_count(conn: &Connection, app: &ObjectRef) -> i32 {
+        proxy::<AccessibleProxyBlocking>(conn, app)
+            .and_then(|acc| acc.child_count())
+            .unwrap_or_default()
+    }
+
+    ///needs gtk3-demo on an accessibility bus, scripts/atspi-test.sh starts one under Xvfb
+    #[test]
+    #[ignore]
+    fn walks_and_invokes_gtk3_demo() {
+        let conn = a11y_connection().expect("no accessibility bus");
+        let (app, pid) = applications(&conn)
+            .into_iter()
+            .find(|(app, _)| {
+                proxy::<AccessibleProxyBlocking>(&conn, app)
+                    .and_then(|acc| acc.name())
+                    .is_ok_and(|name| name == "gtk3-demo")
+            })
+            .expect("gtk3-demo is not running");
+        let mut linux = Linux::new(false, Config::default());
+        linux.topmost = Some(Parent {
+            name: "gtk3-demo".into(),
+            pid: pid.expect("no pid for gtk3-demo"),
+            ..Default::default()
+        });
+
+        let tree = linux.get_elements(&mut Batches::none()).unwrap();
+        let run = tree
+            .nodes
+            .iter()
+            .find(|n| n.include && n.element.name == "Run" && n.element.control == "PushButton")
+            .expect("no Run button was hinted");
+
+        //the selected demo opens in a window of its own
+        let before = window_count(&conn, &app);
+        let obj = &linux.objects[run.id];
+        assert!(invoke_element(&conn, obj, &Action::LeftClick).unwrap());
+        let start = Instant::now();
+        while window_count(&conn, &app) == before && start.elapsed().as_secs() < 3 {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert!(
+            window_count(&conn, &app) > before,
+            "running the demo opened no window"
+        );
+    }
+}
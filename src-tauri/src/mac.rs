@@ -1,189 +1,310 @@
-use std::{cell::Cell, fmt::Display, time::Instant};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{hash_map::Entry, HashMap, HashSet},
+    ffi::c_void,
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use accessibility::{AXAttribute, AXUIElement, AXUIElementAttributes, TreeVisitor, TreeWalkerFlow};
+use accessibility_sys::{
+    kAXCreatedNotification, kAXErrorSuccess, kAXLayoutChangedNotification, kAXMovedNotification,
+    kAXResizedNotification, kAXTitleChangedNotification, kAXUIElementDestroyedNotification,
+    kAXWindowCreatedNotification, AXObserverAddNotification, AXObserverCreate,
+    AXObserverGetRunLoopSource, AXObserverRef, AXUIElementRef,
+};
 use active_win_pos_rs::get_active_window;
-use core_foundation::{base::CFType, string::CFString};
+use core_foundation::{
+    base::{CFType, CFTypeRef, TCFType},
+    boolean::CFBoolean,
+    dictionary::{CFDictionary, CFDictionaryRef},
+    number::CFNumber,
+    runloop::{kCFRunLoopDefaultMode, CFRunLoop, CFRunLoopRunResult, CFRunLoopSource},
+    string::{CFString, CFStringRef},
+};
+use core_graphics::{
+    display::CGDisplay,
+    event::{CGEvent, CGEventFlags, CGEventTapLocation, CGEventType, CGMouseButton, EventField},
+    event_source::{CGEventSource, CGEventSourceStateID},
+    geometry::{CGPoint, CGRect},
+    window::{
+        copy_window_info, kCGNullWindowID, kCGWindowListExcludeDesktopElements,
+        kCGWindowListOptionOnScreenOnly,
+    },
+};
 
-use crate::traits::{AccessibilityCalls, Action, UiElement};
+use crate::{
+    config::{Config, ScanMode},
+    monitor::Rect,
+    rules::{AppRules, Rules},
+    traits::{
+        clean, invoke_in_order, scrolled_to, AccessError, AccessibilityCalls, Action, Batches,
+        Budget, Changes, CoordSpace, ElementTree, Parent, Prerequisite, ShellSurfaces, Strategy,
+        UiElement,
+    },
+};
+
+const ACCESSIBILITY_TRUST: &str = "accessibility-trust";
+///kCGStatusWindowLevel, where menu bar status items are
+const STATUS_LAYER: i64 = 25;
+///what adds, removes, moves or renames hints. the application element gets them for all of its elements
+const CHANGE_NOTIFICATIONS: &[&str] = &[
+    kAXCreatedNotification,
+    kAXUIElementDestroyedNotification,
+    kAXWindowCreatedNotification,
+    kAXMovedNotification,
+    kAXResizedNotification,
+    kAXTitleChangedNotification,
+    kAXLayoutChangedNotification,
+];
 
 pub struct Osx {
     topmost: Option<Parent>,
-    _dock_pid: Option<i32>,
-}
-
-#[derive(Default, Debug, Clone)]
-struct Parent {
-    name: String,
-    pid: i32,
-    x: i32,
-    y: i32,
-    width: i32,
-    height: i32,
+    config: Config,
+    rules: Rules,
+    tree: ElementTree,
+    elements: Vec<AXUIElement>, //indexed by node id
+    //of the current scan, shared with the dock and menu bar
+    budget: Budget,
+    //the application being hinted, whose changes are the only ones forwarded. none for all of them
+    watched: Arc<Mutex<Option<i32>>>,
 }
 
 impl Osx {
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
         Self {
             topmost: None,
-            _dock_pid: None,
+            rules: Rules::new(&config.rules, false).with_offscreen(config.list_offscreen),
+            budget: Budget::new(&config.budget),
+            config,
+            tree: ElementTree::default(),
+            elements: vec![],
+            watched: Arc::default(),
         }
     }
+
+    fn watch_hinted(&self) {
+        *self.watched.lock().unwrap() = match self.config.scan_mode {
+            ScanMode::Topmost => self.topmost.as_ref().map(|t| t.pid),
+            ScanMode::AllWindows => None,
+        };
+    }
 }
 impl AccessibilityCalls for Osx {
-    fn get_elements(&mut self) -> Vec<UiElement> {
+    fn get_elements(&mut self, batches: &mut Batches) -> Result<ElementTree, AccessError> {
         let start = Instant::now();
-        let mut elements = vec![];
-
-        //TODO: once we can overlay on the dock, we can add this back
-        /*        //first get dock
-                if let Some(pid) = self.dock_pid {
-                    let dockvisitor = DockVisitor::new();
-
-                    let els = accessibility::ui_element::AXUIElement::application(pid);
-                    let walker = accessibility::TreeWalker::new();
-
-                    walker.walk(&els, &dockvisitor);
-                    elements.extend(dockvisitor.elements.take());
-                    //SHIFT TO RIGHT OF DOCK
-                    elements.iter_mut().for_each(|e: &mut UiElement| {
-                        e.x_offset = 50;
-                    });
-                }
-        */
-        if self.topmost.is_none() {
-            return elements;
+        if !macos_accessibility_client::accessibility::application_is_trusted() {
+            return Err(AccessError::PermissionDenied);
         }
 
-        let visitor = MyVisitor::new(self.topmost.clone().unwrap());
-        //let root = accessibility::ui_element::AXUIElement::system_wide();
-        let pids = vec![self.topmost.as_ref().unwrap().pid];
-        for pid in pids {
-            let els = accessibility::ui_element::AXUIElement::application(pid);
-            let walker = accessibility::TreeWalker::new();
-
-            walker.walk(&els, &visitor);
+        let visitor = MyVisitor::new(batches.clone(), Budget::new(&self.config.budget));
+        let walker = accessibility::TreeWalker::new();
+        match self.config.scan_mode {
+            ScanMode::Topmost => {
+                let Some(topmost) = self.topmost.clone() else {
+                    return Ok(ElementTree::default());
+                };
+                let els = accessibility::ui_element::AXUIElement::application(topmost.pid);
+                let rules = self.rules.for_app(&topmost.name, topmost.pid, false);
+                visitor.set_root(topmost.pid.to_string(), topmost, rules);
+                walker.walk(&els, &visitor);
+            }
+            ScanMode::AllWindows => {
+                for (window, element) in visible_windows() {
+                    let rules = self.rules.for_app(&window.name, window.pid, false);
+                    visitor.set_root(
+                        format!("{}/w{}", window.pid, window.window_id),
+                        window,
+                        rules,
+                    );
+                    walker.walk(&element, &visitor);
+                }
+            }
+        }
+        self.tree = visitor.tree.take();
+        self.tree.truncated = visitor.budget.borrow().truncated;
+        self.elements = visitor.handles.take();
+        *batches = visitor.batches.replace(Batches::none());
+        self.budget = visitor.budget.into_inner();
+        if self.config.shell_surfaces {
+            self.add_shell_surfaces(batches)?;
         }
-        elements.extend(visitor.elements.take());
         println!(
-            "found {} in {}ms",
-            elements.len(),
+            "found {} out of {} in {}ms",
+            self.tree.nodes.iter().filter(|n| n.include).count(),
+            self.tree.nodes.len(),
             start.elapsed().as_millis()
         );
 
-        elements
-            .iter_mut()
-            .for_each(|a| a.parent = self.topmost.as_ref().unwrap().name.clone());
-
-        elements
+        Ok(self.tree.clone())
     }
 
-    fn invoke(&self, element: &UiElement, action: Action) {
-        //check if in dock
-        /* let els = accessibility::ui_element::AXUIElement::application(self.dock_pid.unwrap());
-        let element1 = element.clone();
-
-        if let Some(ele) = accessibility::ElementFinder::new(
-            move |f| {
-                let a: UiElement = f.into();
-                a.name == element1.name
-                    && a.control == element1.control
-                    && a.x == element1.x
-                    && a.y == element1.y
-            },
-            None,
-        )
-        .find(&els)
-        {
-            let _ = match action {
-                Action::LeftClick => ele.perform_action(&CFString::new("AXPress")),
-                Action::RightClick => ele.perform_action(&CFString::new("AXShowMenu")),
-            };
-        }*/
-        //else in window
-
-        let els =
-            accessibility::ui_element::AXUIElement::application(self.topmost.as_ref().unwrap().pid);
-        let element = element.clone();
-        if let Some(_) = accessibility::ElementFinder::new(
-            move |f| {
-                let a: UiElement = f.into();
-                a.name == element.name
-                    && a.control == element.control
-                    && a.x == element.x
-                    && a.y == element.y
-            },
-            None,
-        )
-        .find(&els)
-        {
-            let x = element.x + element.width / 2;
-            let y = element.y + element.height / 2;
-            let mouse = mouce::Mouse::new();
-            let _ = mouse.move_to(x as usize, y as usize);
-
-            let _ = match action {
-                Action::LeftClick => {
-                    //ele.perform_action(&CFString::new(left))
-                    let _ = mouse.click_button(&mouce::common::MouseButton::Left);
-                }
-                Action::RightClick => {
-                    //ele.perform_action(&CFString::new(right))},
-                    let _ = mouse.click_button(&mouce::common::MouseButton::Right);
-                }
-            };
-        } else {
+    fn invoke(&self, element: &UiElement, action: Action) -> Result<(), AccessError> {
+        let Some(node) = self.tree.resolve(element) else {
             println!("Could not find element to invoke");
+            return Err(AccessError::Stale(element.name.clone()));
+        };
+        let ele = &self.elements[node.id];
+        //elements which are gone return errors
+        if ele.role().is_err() {
+            return Err(AccessError::Stale(element.name.clone()));
         }
+        invoke_in_order(
+            &self.config.invoke_order,
+            element,
+            |strategy| match strategy {
+                Strategy::Native => invoke_native(ele, &element.name, &action),
+                Strategy::Pointer => invoke_pointer(ele, &element.name, &action),
+            },
+        )
     }
 
     fn save_topmost(&mut self) {
-        /*let sys = sysinfo::System::new_with_specifics(
-            RefreshKind::new().with_processes(ProcessRefreshKind::new().with_user()),
-        );
-        let procs = sys.processes();
-
-        //dock
-        if let Some(dock) = procs.values().find(|a| a.name() == "Dock") {
-            self.dock_pid = Some(dock.pid().as_u32() as i32);
-        }*/
-
         let win = get_active_window();
 
         self.topmost = if let Ok(win) = win {
-            let par = Parent {
-                name: win.app_name,
-                pid: win.process_id as i32,
-                x: win.position.x as i32,
-                y: win.position.y as i32,
-                width: win.position.width as i32,
-                height: win.position.height as i32,
-            };
+            let par = Parent::from(win);
             println!("Topmost: {par:?}");
             Some(par)
         } else {
             None
         };
+        self.watch_hinted();
     }
 
-    fn has_permissions(&self) -> bool {
-        macos_accessibility_client::accessibility::application_is_trusted_with_prompt()
+    fn get_topmost(&self) -> Option<Parent> {
+        self.topmost.clone()
+    }
+
+    fn check_prerequisites(&mut self) -> Vec<Prerequisite> {
+        let trusted = macos_accessibility_client::accessibility::application_is_trusted();
+        vec![Prerequisite::new(
+            ACCESSIBILITY_TRUST,
+            "shortkey is allowed to use accessibility",
+            trusted,
+        )
+        .hint(
+            "System Preferences > Security & Privacy > Privacy > Accessibility, and allow shortkey",
+        )
+        .fixable()]
+    }
+
+    fn coord_space(&self) -> CoordSpace {
+        CoordSpace::Logical
+    }
+
+    fn scroll_into_view(&mut self, element: &UiElement) -> Result<UiElement, AccessError> {
+        let Some(node) = self.tree.resolve(element) else {
+            return Err(AccessError::Stale(element.name.clone()));
+        };
+        let ele = &self.elements[node.id];
+        ele.perform_action(&CFString::new("AXScrollToVisible"))
+            .map_err(access_error(&element.name))?;
+        scrolled_to(element, || {
+            //gone once it errors
+            ele.role().ok()?;
+            let (x, y) = get_pos(ele);
+            let (width, height) = get_size(ele);
+            Some(Rect {
+                x,
+                y,
+                width,
+                height,
+            })
+        })
+    }
+
+    fn window_stack(&mut self) -> Vec<Parent> {
+        visible_windows().into_iter().map(|(w, _)| w).collect()
+    }
+
+    fn get_open_menu(&mut self, _from: &UiElement) -> Result<Option<ElementTree>, AccessError> {
+        let Some(topmost) = self.topmost.clone() else {
+            return Ok(None);
+        };
+        let app = AXUIElement::application(topmost.pid);
+        let Some(menu) = open_menu(&app) else {
+            return Ok(None);
+        };
+        let (x, y) = get_pos(&menu);
+        let (width, height) = get_size(&menu);
+        let root = Parent {
+            title: "menu".into(),
+            window_id: format!("{}/menu", topmost.pid),
+            x,
+            y,
+            width,
+            height,
+            ..topmost
+        };
+        let visitor = MyVisitor::new(Batches::none(), Budget::new(&self.config.budget));
+        let rules = self.rules.for_app(&root.name, root.pid, true);
+        visitor.set_root(root.window_id.clone(), root, rules);
+        accessibility::TreeWalker::new().walk(&menu, &visitor);
+        self.tree = visitor.tree.take();
+        self.elements = visitor.handles.take();
+        Ok(Some(self.tree.clone()))
+    }
+
+    fn set_scan_mode(&mut self, mode: ScanMode) {
+        self.config.scan_mode = mode;
+        self.watch_hinted();
+    }
+
+    fn watch_changes(&mut self, changes: Changes) {
+        let watched = self.watched.clone();
+        std::thread::spawn(move || forward_changes(&changes, &watched));
+    }
+
+    fn enable_prerequisite(&mut self, id: &str) -> Result<(), AccessError> {
+        if id != ACCESSIBILITY_TRUST {
+            return Err(AccessError::Backend(format!(
+                "{id} cannot be enabled from here"
+            )));
+        }
+        //shows the system dialog, the user still has to tick the box
+        if macos_accessibility_client::accessibility::application_is_trusted_with_prompt() {
+            Ok(())
+        } else {
+            Err(AccessError::PermissionDenied)
+        }
     }
 }
 
 struct MyVisitor {
     level: Cell<usize>,
-    elements: Cell<Vec<UiElement>>,
-    root: Parent, //we only want the first window (topmost)
+    tree: Cell<ElementTree>,
+    handles: Cell<Vec<AXUIElement>>, //indexed by node id
+    stack: Cell<Vec<usize>>,         //ids of the elements we are inside of
+    root: RefCell<Parent>,           //the window being walked, elements outside it are skipped
+    root_id: RefCell<String>,
+    rules: RefCell<AppRules>, //for the application being walked
+    batches: RefCell<Batches>,
+    budget: RefCell<Budget>,
 }
 
 impl MyVisitor {
-    pub fn new(root: Parent) -> Self {
+    pub fn new(batches: Batches, budget: Budget) -> Self {
         Self {
             level: Cell::new(0),
-            elements: Cell::new(vec![]),
-            root,
+            tree: Cell::new(ElementTree::default()),
+            handles: Cell::new(vec![]),
+            stack: Cell::new(vec![]),
+            root: RefCell::new(Parent::default()),
+            root_id: RefCell::new(String::new()),
+            rules: RefCell::new(AppRules::default()),
+            batches: RefCell::new(batches),
+            budget: RefCell::new(budget),
         }
     }
+
+    ///the next walk is of this window, its elements get ids starting with root_id
+    pub fn set_root(&self, root_id: String, root: Parent, rules: AppRules) {
+        self.root.replace(root);
+        self.root_id.replace(root_id);
+        self.rules.replace(rules);
+    }
 }
 
 impl TreeVisitor for MyVisitor {
@@ -192,13 +313,23 @@ impl TreeVisitor for MyVisitor {
         let new_level = self.level.get() + 1;
         self.level.replace(new_level);
 
-        if let Some(mut uie) = must_include(
-            element,
-            self.root.x,
-            self.root.y,
-            self.root.width,
-            self.root.height,
-        ) {
+        let mut stack = self.stack.take();
+        let parent = stack.last().copied();
+        let mut tree = self.tree.take();
+        //path of child indexes from the application
+        let path = match parent {
+            Some(p) => format!(
+                "{}/{}",
+                tree.nodes[p].element.id,
+                tree.nodes[p].children.len()
+            ),
+            None => self.root_id.borrow().clone(),
+        };
+        let root = self.root.borrow();
+
+        let rules = self.rules.borrow();
+        let scrolled = parent.is_some_and(|p| tree.within(p, &["AXScrollArea"]));
+        let flow = if let Some(mut uie) = must_include(element, &root, &rules, scrolled) {
             //let mut uie: UiElement = element.into();
 
             //for menu
@@ -206,23 +337,60 @@ impl TreeVisitor for MyVisitor {
                 uie.y_offset = 20;
             }
 
-            let mut old = self.elements.take();
-            old.push(uie);
-            self.elements.set(old);
+            uie.id = path;
+            tag_window(&mut uie, &root);
+            let id = tree.add(parent, uie, true);
+            stack.push(id);
 
-            if !must_descend(element) {
+            if !must_descend(element)
+                || !rules.descend(&tree.nodes[id].element)
+                || !self
+                    .budget
+                    .borrow_mut()
+                    .descend(&tree.nodes[id], || has_children(element))
+            {
                 /*println!(
                     "Not descending into {}",
                     AXUIElementDisplay(element.clone())
                 );*/
-                return TreeWalkerFlow::SkipSubtree;
+                TreeWalkerFlow::SkipSubtree
+            } else {
+                TreeWalkerFlow::Continue
             }
         } else {
             //let displ = AXUIElementDisplay(element.clone()).to_string();
             // if displ.contains("main.rs") {
             //  println!("not including {displ}",);
             // }
-        }
+            //keep it for structure
+            let mut uie: UiElement = element.into();
+            uie.id = path;
+            tag_window(&mut uie, &root);
+            let id = tree.add(parent, uie, false);
+            stack.push(id);
+            if self
+                .budget
+                .borrow_mut()
+                .descend(&tree.nodes[id], || has_children(element))
+                && rules.descend(&tree.nodes[id].element)
+            {
+                TreeWalkerFlow::Continue
+            } else {
+                TreeWalkerFlow::SkipSubtree
+            }
+        };
+        //keep what we found so far
+        let flow = if self.budget.borrow_mut().spent(&tree) {
+            TreeWalkerFlow::Exit
+        } else {
+            flow
+        };
+        let mut handles = self.handles.take();
+        handles.push(element.clone());
+        self.handles.set(handles);
+        self.batches.borrow_mut().walked(&tree);
+        self.tree.set(tree);
+        self.stack.set(stack);
 
         /* if get_control(element) == "AXWindow" {
             let found = self.found_window.load(std::sync::atomic::Ordering::Relaxed);
@@ -234,14 +402,184 @@ impl TreeVisitor for MyVisitor {
             }
         }*/
 
-        TreeWalkerFlow::Continue
+        flow
     }
 
     fn exit_element(&self, _element: &AXUIElement) {
         self.level.replace(self.level.get() - 1);
+        let mut stack = self.stack.take();
+        stack.pop();
+        self.stack.set(stack);
+    }
+}
+
+fn tag_window(element: &mut UiElement, root: &Parent) {
+    element.parent = root.name.clone();
+    element.pid = root.pid;
+    element.window_id = root.window_id.clone();
+    element.window = root.title.clone();
+}
+
+///keeps an observer on each application hinted, whose notifications come in on this thread's run loop
+fn forward_changes(changes: &Changes, watched: &Mutex<Option<i32>>) {
+    let mut observers: HashMap<i32, Observer> = HashMap::new();
+    loop {
+        let watched = *watched.lock().unwrap();
+        let pids: HashSet<i32> = match watched {
+            Some(pid) => HashSet::from([pid]),
+            None => visible_windows().into_iter().map(|(w, _)| w.pid).collect(),
+        };
+        observers.retain(|pid, _| pids.contains(pid));
+        for pid in pids {
+            if let Entry::Vacant(entry) = observers.entry(pid) {
+                //apps which are not accessible have nothing to observe, they are tried again next time
+                if let Some(observer) = Observer::new(pid, changes.clone()) {
+                    entry.insert(observer);
+                }
+            }
+        }
+        let interval = Duration::from_millis(300);
+        //without observers it returns at once
+        let ran = CFRunLoop::run_in_mode(unsafe { kCFRunLoopDefaultMode }, interval, false);
+        if let CFRunLoopRunResult::Finished = ran {
+            std::thread::sleep(interval);
+        }
+    }
+}
+
+///an AXObserver of one application, added to the current run loop until dropped
+struct Observer {
+    source: CFRunLoopSource,
+    _observer: CFType,
+    //the callback is handed a pointer to it, so it lives as long as the observer
+    _changed: Box<Changed>,
+}
+
+struct Changed {
+    pid: i32,
+    changes: Changes,
+}
+
+impl Observer {
+    fn new(pid: i32, changes: Changes) -> Option<Self> {
+        let changed = Box::new(Changed { pid, changes });
+        let app = AXUIElement::application(pid);
+        let mut observer: AXObserverRef = std::ptr::null_mut();
+        unsafe {
+            if AXObserverCreate(pid, observed, &mut observer) != kAXErrorSuccess {
+                return None;
+            }
+            let owned = CFType::wrap_under_create_rule(observer as CFTypeRef);
+            let refcon = &*changed as *const Changed as *mut c_void;
+            let added = CHANGE_NOTIFICATIONS
+                .iter()
+                .filter(|name| {
+                    let name = CFString::new(name);
+                    AXObserverAddNotification(
+                        observer,
+                        app.as_concrete_TypeRef(),
+                        name.as_concrete_TypeRef(),
+                        refcon,
+                    ) == kAXErrorSuccess
+                })
+                .count();
+            if added == 0 {
+                return None;
+            }
+            let source = CFRunLoopSource::wrap_under_get_rule(AXObserverGetRunLoopSource(observer));
+            CFRunLoop::get_current().add_source(&source, kCFRunLoopDefaultMode);
+            Some(Observer {
+                source,
+                _observer: owned,
+                _changed: changed,
+            })
+        }
     }
 }
 
+impl Drop for Observer {
+    fn drop(&mut self) {
+        CFRunLoop::get_current().remove_source(&self.source, unsafe { kCFRunLoopDefaultMode });
+    }
+}
+
+unsafe extern "C" fn observed(
+    _observer: AXObserverRef,
+    _element: AXUIElementRef,
+    _notification: CFStringRef,
+    refcon: *mut c_void,
+) {
+    let changed = &*(refcon as *const Changed);
+    changed.changes.lock().unwrap().insert(changed.pid);
+}
+
+///normal windows of other apps on screen, frontmost first, with their accessibility element
+fn visible_windows() -> Vec<(Parent, AXUIElement)> {
+    let options = kCGWindowListOptionOnScreenOnly | kCGWindowListExcludeDesktopElements;
+    let Some(list) = copy_window_info(options, kCGNullWindowID) else {
+        return vec![];
+    };
+    let own = std::process::id() as i32;
+    let mut windows = vec![];
+    for item in list.iter() {
+        let info: CFDictionary<CFString, CFType> =
+            unsafe { CFDictionary::wrap_under_get_rule(*item as CFDictionaryRef) };
+        let value = |key: &'static str| info.find(CFString::from_static_string(key));
+        let number = |key: &'static str| {
+            value(key)
+                .and_then(|v| v.downcast::<CFNumber>())
+                .and_then(|n| n.to_i64())
+        };
+        //layer 0 is normal windows, the rest are menus, the dock etc.
+        if number("kCGWindowLayer") != Some(0) {
+            continue;
+        }
+        let Some(pid) = number("kCGWindowOwnerPID").map(|p| p as i32) else {
+            continue;
+        };
+        let Some(bounds) = value("kCGWindowBounds")
+            .and_then(|b| b.downcast::<CFDictionary>())
+            .and_then(|b| CGRect::from_dict_representation(&b))
+        else {
+            continue;
+        };
+        if pid == own {
+            continue;
+        }
+        let pos = (bounds.origin.x as i32, bounds.origin.y as i32);
+        let size = (bounds.size.width as i32, bounds.size.height as i32);
+
+        //the window server and accessibility dont share ids, so match on the frame
+        let Ok(ax_windows) = AXUIElement::application(pid).windows() else {
+            continue;
+        };
+        let Some(element) = ax_windows
+            .iter()
+            .find(|w| get_pos(w) == pos && get_size(w) == size)
+            .map(|w| (*w).clone())
+        else {
+            continue;
+        };
+        let name = value("kCGWindowOwnerName")
+            .and_then(|v| v.downcast::<CFString>())
+            .map(|s| s.to_string())
+            .unwrap_or_default();
+        let title = element.title().map(|t| t.to_string()).unwrap_or_default();
+        let parent = Parent {
+            name,
+            pid,
+            x: pos.0,
+            y: pos.1,
+            width: size.0,
+            height: size.1,
+            title,
+            window_id: number("kCGWindowNumber").unwrap_or_default().to_string(),
+        };
+        windows.push((parent, element));
+    }
+    windows
+}
+
 fn into_element(
     element: &AXUIElement,
     name: String,
@@ -250,8 +588,11 @@ fn into_element(
     posy: i32,
 ) -> UiElement {
     let size = get_size(element);
+    let editable = is_editable(&role);
+    let opens_menu = opens_menu(element, &role);
 
     UiElement {
+        id: String::new(),
         name,
         x: posx,
         y: posy,
@@ -262,6 +603,15 @@ fn into_element(
         control: role,
         pid: 0,
         parent: "".to_string(),
+        group: String::new(),
+        editable,
+        opens_menu,
+        window_id: String::new(),
+        window: String::new(),
+        space: CoordSpace::Logical,
+        clip: None,
+        offscreen: false,
+        states: vec![],
     }
 }
 
@@ -271,8 +621,11 @@ impl From<&AXUIElement> for UiElement {
         let name = get_name(element);
         let pos = get_pos(element);
         let size = get_size(element);
+        let editable = is_editable(&control);
+        let opens_menu = opens_menu(element, &control);
 
         UiElement {
+            id: String::new(),
             name,
             x: pos.0,
             y: pos.1,
@@ -283,53 +636,131 @@ impl From<&AXUIElement> for UiElement {
             control,
             pid: 0,
             parent: "".to_string(),
+            group: String::new(),
+            editable,
+            opens_menu,
+            window_id: String::new(),
+            window: String::new(),
+            space: CoordSpace::Logical,
+            clip: None,
+            offscreen: false,
+            states: vec![],
         }
     }
 }
 
+///menu bar items, menu buttons and menu items with a submenu
+fn opens_menu(element: &AXUIElement, role: &str) -> bool {
+    match role {
+        "AXMenuBarItem" | "AXMenuButton" | "AXPopUpButton" => true,
+        "AXMenuItem" => has_children(element),
+        _ => false,
+    }
+}
+
+///context menus are children of the application while open. menu bar menus are always there,
+///the open one is under the selected menu bar item
+fn open_menu(app: &AXUIElement) -> Option<AXUIElement> {
+    let children = app.children().ok()?;
+    if let Some(menu) = children.iter().find(|c| get_role(c) == "AXMenu") {
+        return Some(innermost_menu((*menu).clone()));
+    }
+    let bar = children.iter().find(|c| get_role(c) == "AXMenuBar")?;
+    let items = bar.children().ok()?;
+    let item = items.iter().find(|i| is_selected(i))?;
+    let menu = item.children().ok()?.iter().next().map(|m| (*m).clone())?;
+    Some(innermost_menu(menu))
+}
+
+///an open submenu is under the selected item of its menu
+fn innermost_menu(menu: AXUIElement) -> AXUIElement {
+    let submenu = menu.children().ok().and_then(|items| {
+        let item = items
+            .iter()
+            .find(|i| get_role(i) == "AXMenuItem" && is_selected(i))?;
+        let children = item.children().ok()?;
+        let submenu = children.iter().next().map(|m| (*m).clone());
+        submenu
+    });
+    match submenu {
+        Some(submenu) => innermost_menu(submenu),
+        None => menu,
+    }
+}
+
+fn is_selected(element: &AXUIElement) -> bool {
+    element
+        .attribute(&AXAttribute::new(&CFString::new("AXSelected")))
+        .ok()
+        .and_then(|v| v.downcast::<CFBoolean>())
+        .is_some_and(bool::from)
+}
+
+///the states rules can match on but focusable, which mac has no attribute for.
+///each is a call to the application
+fn states(element: &AXUIElement, role: &str) -> Vec<String> {
+    let flag = |name: &str| {
+        element
+            .attribute(&AXAttribute::new(&CFString::new(name)))
+            .ok()
+            .and_then(|v| v.downcast::<CFBoolean>())
+            .is_some_and(bool::from)
+    };
+    //the value of a check box is 1 when checked, other controls' values mean something else
+    let checked = matches!(role, "AXCheckBox" | "AXRadioButton")
+        && element
+            .attribute(&AXAttribute::new(&CFString::new("AXValue")))
+            .ok()
+            .and_then(|v| v.downcast::<CFNumber>())
+            .and_then(|n| n.to_i64())
+            == Some(1);
+    [
+        ("enabled", flag("AXEnabled")),
+        ("focused", flag("AXFocused")),
+        ("selected", is_selected(element)),
+        ("checked", checked),
+        ("expanded", flag("AXExpanded")),
+    ]
+    .into_iter()
+    .filter(|(_, has)| *has)
+    .map(|(name, _)| name.to_string())
+    .collect()
+}
+
+fn is_editable(role: &str) -> bool {
+    matches!(role, "AXTextField" | "AXTextArea" | "AXComboBox")
+}
+
+///scrolled is whether it is in a scroll area, which can bring it into view
 fn must_include(
     element: &AXUIElement,
-    x: i32,
-    y: i32,
-    width: i32,
-    height: i32,
+    root: &Parent,
+    rules: &AppRules,
+    scrolled: bool,
 ) -> Option<UiElement> {
     let role = get_role(element);
-    let valid_role = match role.as_str() {
-        "AXApplication" => false,
-        "AXMenuItem" => false,
-        "AXWindow" => false,  //otherwise highlights entire window
-        "AXWebArea" => false, //otherwise highlights entire window for vscode
-        "AXOutline" => false, //otherwise highlights sidebar of finder
-        "AXGroup" => false,   //normally includes other items
-        "AXRow" => false,     //normally includes other items
-
-        //  "AXStaticText" | "AXMenu" | "AXToolbar"  | "AXWebArea"
-        // | "AXTabGroup" | "AXOutline" | "AXHeading" => false,
-
-        // "AXRow" | "AXGroup" //vscode uses these two for filelist
-        "AXButton" | "AXCheckBox" | "AXRadioButton" | "AXPopUpButton" => true,
-        _ => true,
-    };
-    if !valid_role {
-        return None;
-    }
-
-    let name = get_name(element).replace(|a: char| !(a.is_alphanumeric() || a.is_whitespace()), "");
-    if name.is_empty() {
-        // println!("Excluding {} with no name",AXUIElementDisplay(element.clone()));
-        return None;
+    let name = clean(&get_name(element));
+    let (posx, posy) = get_pos(element);
+    let mut uie = into_element(element, name, role, posx, posy);
+    if rules.wants_states() {
+        uie.states = states(element, &uie.control);
     }
+    rules.include(&uie).ok()?;
     //check bounds
-    let (posx, posy) = get_pos(element);
-    let in_bounds = role == "AXMenuBarItem"
-        || (posx >= x && posx <= x + width && posy >= y && posy <= y + height);
-
-    if !in_bounds {
+    let in_bounds = uie.control == "AXMenuBarItem"
+        || (posx >= root.x
+            && posx <= root.x + root.width
+            && posy >= root.y
+            && posy <= root.y + root.height);
+    if !in_bounds && !(rules.offscreen && scrolled) {
         return None;
     }
+    uie.offscreen = !in_bounds;
+    Some(uie)
+}
 
-    Some(into_element(element, name, role, posx, posy))
+fn has_children(element: &AXUIElement) -> bool {
+    element.children().is_ok_and(|c| c.len() > 0)
 }
 
 fn must_descend(element: &AXUIElement) -> bool {
@@ -370,6 +801,106 @@ fn get_name(element: &AXUIElement) -> String {
 fn get_role(element: &AXUIElement) -> String {
     element.role().unwrap_or(CFString::from("")).to_string()
 }
+
+///perform the element's own action, NoAction if it has none for this action
+fn invoke_native(ele: &AXUIElement, name: &str, action: &Action) -> Result<(), AccessError> {
+    let error = access_error(name);
+    let ax_action = match action {
+        Action::LeftClick => "AXPress",
+        Action::RightClick => "AXShowMenu",
+        Action::Focus => {
+            let focused = AXAttribute::new(&CFString::new("AXFocused"));
+            return ele
+                .set_attribute(&focused, CFBoolean::true_value().as_CFType())
+                .map_err(error);
+        }
+        Action::Type(text) => {
+            let _ = ele.set_attribute(
+                &AXAttribute::new(&CFString::new("AXFocused")),
+                CFBoolean::true_value().as_CFType(),
+            );
+            let value = AXAttribute::new(&CFString::new("AXValue"));
+            return ele
+                .set_attribute(&value, CFString::new(text).as_CFType())
+                .map_err(error);
+        }
+        _ => return Err(AccessError::NoAction(name.to_string())),
+    };
+    let has_action = ele
+        .action_names()
+        .is_ok_and(|names| names.iter().any(|n| n.to_string() == ax_action));
+    if !has_action {
+        return Err(AccessError::NoAction(name.to_string()));
+    }
+    ele.perform_action(&CFString::new(ax_action)).map_err(error)
+}
+
+fn invoke_pointer(ele: &AXUIElement, name: &str, action: &Action) -> Result<(), AccessError> {
+    if let Action::Focus | Action::Type(_) = action {
+        return Err(AccessError::NoAction(name.to_string()));
+    }
+    //it may have moved since we scanned
+    let (posx, posy) = get_pos(ele);
+    let (width, height) = get_size(ele);
+    let x = posx + width / 2;
+    let y = posy + height / 2;
+    let mouse = mouce::Mouse::new();
+    let injection = |e| AccessError::InjectionFailed(format!("{:?}", e));
+    mouse.move_to(x as usize, y as usize).map_err(injection)?;
+
+    match action {
+        Action::LeftClick => mouse
+            .click_button(&mouce::common::MouseButton::Left)
+            .map_err(injection),
+        Action::RightClick => mouse
+            .click_button(&mouce::common::MouseButton::Right)
+            .map_err(injection),
+        Action::MiddleClick => mouse
+            .click_button(&mouce::common::MouseButton::Middle)
+            .map_err(injection),
+        Action::Hover | Action::Focus | Action::Type(_) => Ok(()),
+        Action::DoubleClick => {
+            post_click(x, y, 1, CGEventFlags::CGEventFlagNull)?;
+            post_click(x, y, 2, CGEventFlags::CGEventFlagNull)
+        }
+        Action::ShiftClick => post_click(x, y, 1, CGEventFlags::CGEventFlagShift),
+        Action::CtrlClick => post_click(x, y, 1, CGEventFlags::CGEventFlagControl),
+    }
+}
+
+//AXError codes from AXError.h
+const AX_ERROR_INVALID_UI_ELEMENT: i32 = -25202;
+const AX_ERROR_CANNOT_COMPLETE: i32 = -25204;
+const AX_ERROR_API_DISABLED: i32 = -25211;
+
+///maps errors about the named element, which is stale when it went away
+fn access_error(name: &str) -> impl Fn(accessibility::Error) -> AccessError + '_ {
+    move |e| match e {
+        accessibility::Error::Ax(AX_ERROR_INVALID_UI_ELEMENT) => {
+            AccessError::Stale(name.to_string())
+        }
+        //what the app returns when it is too busy to answer
+        accessibility::Error::Ax(AX_ERROR_CANNOT_COMPLETE) => AccessError::Timeout,
+        accessibility::Error::Ax(AX_ERROR_API_DISABLED) => AccessError::PermissionDenied,
+        e => AccessError::Backend(format!("{:?}", e)),
+    }
+}
+
+///mouce can not set the click count or modifiers, so post the events ourselves
+fn post_click(x: i32, y: i32, count: i64, flags: CGEventFlags) -> Result<(), AccessError> {
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+        .map_err(|_| AccessError::InjectionFailed("no event source".into()))?;
+    let point = CGPoint::new(x as f64, y as f64);
+    for typ in [CGEventType::LeftMouseDown, CGEventType::LeftMouseUp] {
+        let event = CGEvent::new_mouse_event(source.clone(), typ, point, CGMouseButton::Left)
+            .map_err(|_| AccessError::InjectionFailed("could not create mouse event".into()))?;
+        event.set_integer_value_field(EventField::MOUSE_EVENT_CLICK_STATE, count);
+        event.set_flags(flags);
+        event.post(CGEventTapLocation::HID);
+    }
+    Ok(())
+}
+
 fn get_pos(element: &AXUIElement) -> (i32, i32) {
     let mut pos = (0, 0);
     if let Ok(pos2) = element.attribute(&AXAttribute::new(&CFString::new("AXPosition"))) {
@@ -460,28 +991,106 @@ impl Display for AXUIElementDisplay {
     }
 }
 
-struct DockVisitor {
-    elements: Cell<Vec<UiElement>>,
-}
-
-impl DockVisitor {
-    pub fn new() -> Self {
-        Self {
-            elements: Cell::new(vec![]),
+impl ShellSurfaces for Osx {
+    ///the dock and the status items in the menu bar, walked on with what is left of the budget
+    fn add_shell_surfaces(&mut self, batches: &mut Batches) -> Result<(), AccessError> {
+        let start = Instant::now();
+        let first = self.tree.nodes.len();
+        let visitor = MyVisitor::new(batches.clone(), self.budget.clone());
+        visitor.tree.set(std::mem::take(&mut self.tree));
+        visitor.handles.set(std::mem::take(&mut self.elements));
+        let walker = accessibility::TreeWalker::new();
+        for (root, element) in shell_surfaces() {
+            let rules = self.rules.for_app(&root.name, root.pid, false);
+            visitor.set_root(format!("{}/{}", root.pid, root.window_id), root, rules);
+            walker.walk(&element, &visitor);
         }
+        self.tree = visitor.tree.take();
+        self.elements = visitor.handles.take();
+        *batches = visitor.batches.replace(Batches::none());
+        self.budget = visitor.budget.into_inner();
+        self.tree.truncated = self.budget.truncated;
+        println!(
+            "got {} dock and menu bar elements in {}ms",
+            self.tree.nodes.len() - first,
+            start.elapsed().as_millis()
+        );
+        Ok(())
     }
 }
 
-impl TreeVisitor for DockVisitor {
-    fn enter_element(&self, element: &AXUIElement) -> TreeWalkerFlow {
-        let uie: UiElement = element.into();
-        //println!("{uie:?}");
-        let mut old = self.elements.take();
-        old.push(uie);
-        self.elements.set(old);
-
-        TreeWalkerFlow::Continue
+///the dock, and the menu bar extras of apps with status items. they are not inside a window,
+///so their roots cover every display
+fn shell_surfaces() -> Vec<(Parent, AXUIElement)> {
+    let Some(list) = copy_window_info(kCGWindowListOptionOnScreenOnly, kCGNullWindowID) else {
+        return vec![];
+    };
+    let displays = CGDisplay::active_displays()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|id| {
+            let bounds = CGDisplay::new(id).bounds();
+            Rect {
+                x: bounds.origin.x as i32,
+                y: bounds.origin.y as i32,
+                width: bounds.size.width as i32,
+                height: bounds.size.height as i32,
+            }
+        })
+        .collect::<Vec<_>>();
+    let Some(screen) = Rect::union(&displays) else {
+        return vec![];
+    };
+    let own = std::process::id() as i32;
+    let mut seen = HashSet::new();
+    let mut surfaces = vec![];
+    for item in list.iter() {
+        let info: CFDictionary<CFString, CFType> =
+            unsafe { CFDictionary::wrap_under_get_rule(*item as CFDictionaryRef) };
+        let value = |key: &'static str| info.find(CFString::from_static_string(key));
+        let number = |key: &'static str| {
+            value(key)
+                .and_then(|v| v.downcast::<CFNumber>())
+                .and_then(|n| n.to_i64())
+        };
+        let Some(pid) = number("kCGWindowOwnerPID").map(|p| p as i32) else {
+            continue;
+        };
+        let name = value("kCGWindowOwnerName")
+            .and_then(|v| v.downcast::<CFString>())
+            .map(|s| s.to_string())
+            .unwrap_or_default();
+        let surface = match name.as_str() {
+            "Dock" => "dock",
+            _ if number("kCGWindowLayer") == Some(STATUS_LAYER) => "menu bar",
+            _ => continue,
+        };
+        if pid == own || !seen.insert(pid) {
+            continue;
+        }
+        let app = AXUIElement::application(pid);
+        let element = match surface {
+            "dock" => app,
+            //each app's status items are in a menu bar of their own
+            _ => {
+                let extras = app.attribute(&AXAttribute::new(&CFString::new("AXExtrasMenuBar")));
+                match extras.ok().and_then(|e| e.downcast::<AXUIElement>()) {
+                    Some(extras) => extras,
+                    None => continue,
+                }
+            }
+        };
+        let root = Parent {
+            name,
+            pid,
+            x: screen.x,
+            y: screen.y,
+            width: screen.width,
+            height: screen.height,
+            title: surface.into(),
+            window_id: surface.into(),
+        };
+        surfaces.push((root, element));
     }
-
-    fn exit_element(&self, _element: &AXUIElement) {}
+    surfaces
 }
@@ -1,78 +1,150 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod cache;
+mod config;
+mod dedup;
+mod fixture;
+#[cfg(target_os = "linux")]
+mod linux;
 #[cfg(target_os = "macos")]
 mod mac;
+mod monitor;
+mod occlusion;
+mod rules;
+mod snapshot;
 #[cfg(target_os = "windows")]
 mod windows;
 
 mod traits;
+#[cfg(target_os = "linux")]
+mod wayland;
+#[cfg(target_os = "linux")]
+mod xtest;
 
+use config::{Config, ScanMode};
 use easier::prelude::*;
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     error::Error,
+    path::{Path, PathBuf},
     sync::{
-        mpsc::{Receiver, Sender},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
         Mutex,
     },
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
+use cache::Cache;
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use monitor::{Monitor, Rect};
+use occlusion::Hidden;
 use serde::{Deserialize, Serialize};
+use snapshot::Snapshot;
 use tauri::{
     App, AppHandle, CustomMenuItem, Manager, PhysicalPosition, PhysicalSize, Position, Size, State,
     SystemTray, SystemTrayEvent, SystemTrayMenu, Window,
 };
-use traits::{AccessibilityCalls, Action, UiElement};
+use traits::{
+    AccessError, AccessibilityCalls, Action, Batches, Changes, CoordSpace, ElementTree, Parent,
+    Prerequisite, Truncation, UiElement,
+};
 
 struct AppState {
     input: String,
-    results: Vec<String>,
+    ///every hint so far, which the input is matched against
+    hints: Vec<Hint>,
     sender: Sender<Message>,
+    ///made up monitor layout, instead of asking the os
+    monitors: Option<Vec<Monitor>>,
+    ///what the backend measures windows in, known once the worker has the app handle
+    space: CoordSpace,
+}
+
+struct Args {
+    debug: bool,
+    ///canned elements instead of the screen
+    fixture: Option<PathBuf>,
+    ///snapshot to replay instead of the screen
+    replay: Option<PathBuf>,
+    ///save a snapshot of every scan here
+    record: Option<PathBuf>,
+    config: Config,
+    monitors: Option<Vec<Monitor>>,
 }
 
 fn main() {
     println!("starting");
 
-    let debug = false;
-    let show_taskbar = true;
+    let monitors = arg_value("--monitors").and_then(|path| match monitor::load(path.as_ref()) {
+        Ok(monitors) => Some(monitors),
+        Err(e) => {
+            println!("could not load monitors {path:?}: {e}");
+            None
+        }
+    });
+    let args = Args {
+        debug: std::env::args().any(|a| a == "--debug"),
+        fixture: arg_value("--fixture").map(PathBuf::from),
+        replay: arg_value("--replay").map(PathBuf::from),
+        record: arg_value("--record").map(PathBuf::from),
+        config: Config::load_or_default(arg_value("--config").map(PathBuf::from).as_deref()),
+        monitors: monitors.clone(),
+    };
+    let tray = setup_system_tray(args.config.scan_mode);
     let (sender, rec) = std::sync::mpsc::channel();
     std::thread::spawn(move || {
-        worker(rec, debug, show_taskbar);
+        worker(rec, args);
     });
     let state = AppState {
         input: String::new(),
-        results: vec![],
+        hints: vec![],
         sender,
+        monitors,
+        space: CoordSpace::default(),
     };
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![update_input, choice, hide, show])
+        .invoke_handler(tauri::generate_handler![
+            update_input,
+            choice,
+            hide,
+            show,
+            enable_prerequisite
+        ])
         .manage(Mutex::new(state))
-        .system_tray(setup_system_tray())
+        .system_tray(tray)
         .on_system_tray_event(handle_system_tray) // <- handling the system tray events
         .setup(setup_tauri)
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
+///value following a flag on the command line e.g. --fixture screen.json
+fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|a| a != flag);
+    args.next()?;
+    args.next()
+}
+
 fn setup_tauri(app: &mut App) -> Result<(), Box<dyn Error>> {
     #[cfg(target_os = "macos")]
     app.set_activation_policy(tauri::ActivationPolicy::Accessory); //dont show in dock
                                                                    //listen to get fullscreen
     let ah = app.app_handle();
-    app.listen_global("go_full", move |_event| {
-        if let Some(window) = ah.get_focused_window() {
-            set_full_size(&window);
+    app.listen_global("go_full", move |event| {
+        let area = event
+            .payload()
+            .and_then(|p| serde_json::from_str::<Monitor>(p).ok());
+        if let (Some(window), Some(area)) = (ah.get_focused_window(), area) {
+            set_full_size(&window, area);
         }
     });
 
     let state: State<Mutex<AppState>> = app.state();
+    let state = state.lock().unwrap();
     let window = app.get_window("main").unwrap();
-    set_output_size(&window);
+    set_output_size(&window, &state.monitors, state.space);
 
-    let state = state.lock().unwrap();
     state
         .sender
         .send(Message::AppHandle(app.app_handle()))
@@ -81,9 +153,17 @@ fn setup_tauri(app: &mut App) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn setup_system_tray() -> SystemTray {
+fn setup_system_tray(scan_mode: ScanMode) -> SystemTray {
+    let mut all_windows = CustomMenuItem::new("all_windows".to_string(), "Scan all windows");
+    if scan_mode == ScanMode::AllWindows {
+        all_windows = all_windows.selected();
+    }
+    let snapshot = CustomMenuItem::new("snapshot".to_string(), "Save snapshot");
     let quit = CustomMenuItem::new("quit".to_string(), "Quit");
-    let tray_menu = SystemTrayMenu::new().add_item(quit);
+    let tray_menu = SystemTrayMenu::new()
+        .add_item(all_windows)
+        .add_item(snapshot)
+        .add_item(quit);
     SystemTray::new().with_menu(tray_menu)
 }
 
@@ -93,6 +173,20 @@ fn handle_system_tray(app: &tauri::AppHandle, event: tauri::SystemTrayEvent) {
         SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
             "quit" => app.exit(0),
             "toggle" => toggle_window(app),
+            "snapshot" => {
+                let state: State<Mutex<AppState>> = app.state();
+                let res = state.lock().unwrap().sender.send(Message::SaveSnapshot);
+                if let Err(e) = res {
+                    eprintln!("error sending message: {:?}", e);
+                }
+            }
+            "all_windows" => {
+                let state: State<Mutex<AppState>> = app.state();
+                let res = state.lock().unwrap().sender.send(Message::ToggleScanMode);
+                if let Err(e) = res {
+                    eprintln!("error sending message: {:?}", e);
+                }
+            }
             _ => {}
         },
         _ => {}
@@ -110,31 +204,25 @@ fn toggle_window(app: &tauri::AppHandle) {
     }
 }
 #[tauri::command]
-fn update_input(input: &str, state: tauri::State<Mutex<AppState>>) {
-    state.lock().unwrap().input = input.to_string();
-    state.lock().unwrap().results = vec![];
-    let res = state
-        .lock()
-        .unwrap()
-        .sender
-        .send(Message::UpdateInput(input.to_string()));
-    if let Err(e) = res {
-        eprintln!("error sending message: {:?}", e);
-    }
-}
-
-impl From<&str> for Action {
-    fn from(s: &str) -> Self {
-        match s {
-            "LeftClick" => Action::LeftClick,
-            "RightClick" => Action::RightClick,
-            _ => Action::LeftClick,
-        }
-    }
+fn update_input(input: &str, state: tauri::State<Mutex<AppState>>, app: AppHandle) {
+    let mut state = state.lock().unwrap();
+    state.input = input.to_string();
+    //matched here, as the worker may be busy walking
+    emit_results(&app, &state);
 }
 
 #[tauri::command]
-fn choice(choice: &str, action: &str, state: tauri::State<Mutex<AppState>>, app: AppHandle) {
+fn choice(
+    choice: &str,
+    action: &str,
+    text: Option<String>,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let action = match text {
+        Some(text) if action == "Type" => Action::Type(text),
+        _ => Action::try_from(action)?,
+    };
     hide_window(app);
     std::thread::sleep(Duration::from_millis(100)); //wait to hide window
     let state = state.lock().unwrap();
@@ -142,58 +230,115 @@ fn choice(choice: &str, action: &str, state: tauri::State<Mutex<AppState>>, app:
     println!("choice:{choice}");
     let res = state
         .sender
-        .send(Message::Invoke(choice.to_string(), action.into()));
+        .send(Message::Invoke(choice.to_string(), action));
     if let Err(e) = res {
         eprintln!("error sending message: {:?}", e);
     }
+    Ok(())
 }
 
 #[tauri::command]
-fn hide(app: AppHandle) {
+fn hide(state: tauri::State<Mutex<AppState>>, app: AppHandle) {
     hide_window(app);
+    let _ = state.lock().unwrap().sender.send(Message::Hidden);
 }
 
 #[tauri::command]
 fn show(state: tauri::State<Mutex<AppState>>, app: AppHandle) {
-    let state = state.lock().unwrap();
+    let mut state = state.lock().unwrap();
+    state.input.clear();
+    state.hints.clear();
+    //before anything else, so the target is recorded while it still has focus
     state.sender.send(Message::SaveTopmost).unwrap();
+    state.sender.send(Message::CheckStatus).unwrap();
 
     std::thread::sleep(Duration::from_millis(100)); //wait to get topmost to finish
     state.sender.send(Message::RequestHints).unwrap();
 
     app.emit_all("show", ()).unwrap();
     let window = app.get_window("main").unwrap();
-    set_output_size(&window);
+    set_output_size(&window, &state.monitors, state.space);
     show_window(app.clone());
 
     window.set_focus().unwrap();
 }
 
-///full screen for hints
-fn set_full_size(window: &Window) {
-    let monitor = window.current_monitor().unwrap().unwrap();
-    let size = monitor.size();
-    eprintln!("setting full size {size:?}");
+#[tauri::command]
+fn enable_prerequisite(id: &str, state: tauri::State<Mutex<AppState>>) {
+    let res = state
+        .lock()
+        .unwrap()
+        .sender
+        .send(Message::EnablePrerequisite(id.to_string()));
+    if let Err(e) = res {
+        eprintln!("error sending message: {:?}", e);
+    }
+}
+
+///the given layout, else the monitors from the os
+fn monitors(window: &Window, layout: &Option<Vec<Monitor>>) -> Vec<Monitor> {
+    if let Some(layout) = layout {
+        return layout.clone();
+    }
+    window
+        .available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(|m| Monitor {
+            bounds: Rect {
+                x: m.position().x,
+                y: m.position().y,
+                width: m.size().width as i32,
+                height: m.size().height as i32,
+            },
+            scale: m.scale_factor(),
+        })
+        .collect()
+}
+
+///cover the area with hints, it can be any monitor or all of them
+fn set_full_size(window: &Window, area: Monitor) {
+    eprintln!("setting full size {area:?}");
+    let area = area.bounds;
 
     window.hide().unwrap();
     window
         .set_size(Size::Physical(PhysicalSize {
-            width: size.width,
-            height: size.height,
+            width: area.width as u32,
+            height: area.height as u32,
         }))
         .unwrap();
     window
-        .set_position(Position::Physical(PhysicalPosition { x: 0, y: 0 }))
+        .set_position(Position::Physical(PhysicalPosition {
+            x: area.x,
+            y: area.y,
+        }))
         .unwrap();
     window.show().unwrap();
 }
 
-///size for only output
-fn set_output_size(window: &Window) {
-    let monitor = window.current_monitor().unwrap().unwrap();
-    let size = monitor.size();
-    let wid = 900;
-    let hei = 300;
+///size for only output, centred on the monitor of the focused window
+///space is what the active window is measured in, the same as the backend's
+fn set_output_size(window: &Window, layout: &Option<Vec<Monitor>>, space: CoordSpace) {
+    let monitors = monitors(window, layout);
+    let target = active_win_pos_rs::get_active_window().ok().map(|w| {
+        let rect = Rect {
+            x: w.position.x as i32,
+            y: w.position.y as i32,
+            width: w.position.width as i32,
+            height: w.position.height as i32,
+        };
+        monitor::to_physical(&monitors, space, rect)
+    });
+    let monitor = monitor::overlay_area(&monitors, target, config::OverlayMode::TargetMonitor)
+        .unwrap_or(Monitor {
+            bounds: Rect::default(),
+            scale: 1.0,
+        });
+    //the same size to the eye on any scale
+    let wid = (900.0 * monitor.scale) as u32;
+    let hei = (300.0 * monitor.scale) as u32;
+    let monitor = monitor.bounds;
 
     window
         .set_size(Size::Physical(PhysicalSize {
@@ -203,116 +348,301 @@ fn set_output_size(window: &Window) {
         .unwrap();
     window
         .set_position(Position::Physical(PhysicalPosition {
-            x: (size.width - wid) as i32 / 2,
-            y: (size.height - hei) as i32 / 2,
+            x: monitor.x + (monitor.width - wid as i32) / 2,
+            y: monitor.y + (monitor.height - hei as i32) / 2,
         }))
         .unwrap();
 }
 
 enum Message {
     AppHandle(AppHandle),
-    UpdateInput(String),
     RequestHints,
+    ///the overlay closed, so its hints need no more updates
+    Hidden,
     Invoke(String, Action),
     SaveTopmost,
+    SaveSnapshot,
+    ///check the prerequisites again and report them
+    CheckStatus,
+    ///between the topmost window and all windows
+    ToggleScanMode,
+    EnablePrerequisite(String),
 }
-fn create_hints(elements: &[UiElement]) -> Vec<Hint> {
-    let mut index = HashSet::new();
-    let hints: Vec<Hint> = elements
-        .iter()
-        //.take(600) //hard limit (which is less than 26*26 or 2 chars)
-        .map(|e| e.into())
-        .map(|e: Hint| {
-            let mut e = e;
-            let chars = e.text.chars().filter(|a| a.is_alphabetic()).to_vec();
-            let one = chars.iter().take(1).collect::<String>().to_uppercase();
-            let two = chars.iter().take(2).collect::<String>().to_uppercase();
-
-            if !one.is_empty() && !index.contains(&one) {
-                e.hint = one.clone();
-                index.insert(one);
-                return e;
-            }
-            //go through each of the letters, and return the first one that isn't in the index
-            for c in 'A'..='Z' {
-                if !index.contains(&c.to_string()) {
-                    e.hint = c.to_string();
-                    index.insert(c.to_string());
-                    return e;
-                }
-            }
-            //else try 2
-            if !two.is_empty() && !index.contains(&two) {
-                e.hint = two.clone();
-                index.insert(two);
-                return e;
+///gives out hint labels in walk order, an element which got one keeps it
+#[derive(Default)]
+struct Hinter {
+    used: HashSet<String>,
+    ///by element
+    given: HashMap<String, String>,
+}
+
+impl Hinter {
+    ///fails once every label of up to 3 letters is given out
+    fn hint(&mut self, element: &UiElement) -> Result<Hint, String> {
+        let mut hint: Hint = element.into();
+        //fixtures may have no ids
+        let key = if element.id.is_empty() {
+            format!(
+                "{}/{}/{}/{}",
+                element.name, element.control, element.x, element.y
+            )
+        } else {
+            element.id.clone()
+        };
+        if let Some(label) = self.given.get(&key) {
+            hint.hint = label.clone();
+            return Ok(hint);
+        }
+        hint.hint = self
+            .next_label(&hint.text)
+            .ok_or_else(|| format!("no labels left for {}", element.name))?;
+        self.used.insert(hint.hint.clone());
+        self.given.insert(key, hint.hint.clone());
+        Ok(hint)
+    }
+
+    ///forget the labels of elements which are no longer hinted, so they can be given out again
+    fn keep(&mut self, hints: &[Hint]) {
+        let labels: HashSet<&str> = hints.iter().map(|h| h.hint.as_str()).collect();
+        self.given
+            .retain(|_, label| labels.contains(label.as_str()));
+        self.used.retain(|label| labels.contains(label.as_str()));
+    }
+
+    fn next_label(&self, text: &str) -> Option<String> {
+        let chars = text.chars().filter(|a| a.is_alphabetic()).to_vec();
+        let one = chars.iter().take(1).collect::<String>().to_uppercase();
+        let two = chars.iter().take(2).collect::<String>().to_uppercase();
+
+        if !one.is_empty() && !self.used.contains(&one) {
+            return Some(one);
+        }
+        //go through each of the letters, and return the first one that isn't in the index
+        for c in 'A'..='Z' {
+            if !self.used.contains(&c.to_string()) {
+                return Some(c.to_string());
             }
-            //go through every combination of 2 letters
-            for c1 in 'A'..='Z' {
-                for c2 in 'A'..='Z' {
-                    let s = format!("{}{}", c1, c2);
-                    if !index.contains(&s) {
-                        e.hint = s.clone();
-                        index.insert(s);
-                        return e;
-                    }
+        }
+        //else try 2
+        if !two.is_empty() && !self.used.contains(&two) {
+            return Some(two);
+        }
+        //go through every combination of 2 letters
+        for c1 in 'A'..='Z' {
+            for c2 in 'A'..='Z' {
+                let s = format!("{}{}", c1, c2);
+                if !self.used.contains(&s) {
+                    return Some(s);
                 }
             }
-            //go through every combination of 3 letters
-            for c1 in 'A'..='Z' {
-                for c2 in 'A'..='Z' {
-                    for c3 in 'A'..='Z' {
-                        let s = format!("{}{}{}", c1, c2, c3);
-                        if !index.contains(&s) {
-                            e.hint = s.clone();
-                            index.insert(s);
-                            return e;
-                        }
+        }
+        //go through every combination of 3 letters
+        for c1 in 'A'..='Z' {
+            for c2 in 'A'..='Z' {
+                for c3 in 'A'..='Z' {
+                    let s = format!("{}{}{}", c1, c2, c3);
+                    if !self.used.contains(&s) {
+                        return Some(s);
                     }
                 }
             }
+        }
 
-            //should not arrive here
-            unreachable!("should be less than 26*26 elements");
-        })
-        .collect();
-    hints
+        None
+    }
 }
-fn worker(rec: Receiver<Message>, debug: bool, show_taskbar: bool) {
+
+fn worker(rec: Receiver<Message>, args: Args) {
     //windows::get_elements_mozilla();
 
     let mut app = None;
-    let mut auto = get_accessibility(debug, show_taskbar);
-    auto.has_permissions();
+    let mut auto = get_accessibility(&args);
+    let changes = Changes::default();
+    auto.watch_changes(changes.clone());
+    let mut cache = Cache::new(Duration::from_millis(args.config.cache_millis));
+    let mut scan_mode = args.config.scan_mode;
     let mut hints: Vec<Hint> = vec![];
+    let mut tree = ElementTree::default();
     let mut elements: Vec<UiElement> = vec![];
+    let mut live: Option<Live> = None;
     loop {
-        if let Ok(msg) = rec.recv() {
+        //cached hints are walked again as soon as nothing else is waiting
+        let wait = match live.as_ref() {
+            Some(live) if live.cached => Duration::ZERO,
+            _ => Live::INTERVAL,
+        };
+        let received = rec.recv_timeout(wait);
+        //nothing to do, so check whether what is hinted changed
+        if let (Err(RecvTimeoutError::Timeout), Some(live), Some(app)) =
+            (&received, live.as_mut(), app.as_ref())
+        {
+            let changed = changes.lock().unwrap().drain().to_vec();
+            for pid in changed.iter() {
+                cache.invalidate(*pid);
+            }
+            if live.cached || (!changed.is_empty() && live.pid.is_none_or(|p| changed.contains(&p)))
+            {
+                refresh_hints(
+                    app,
+                    auto.as_mut(),
+                    live,
+                    &mut cache,
+                    &mut tree,
+                    &mut elements,
+                    &mut hints,
+                );
+            }
+        }
+        if let Ok(msg) = received {
             // windows::get_elements_mozilla();
 
             match msg {
-                Message::AppHandle(ah) => app = Some(ah),
-                Message::UpdateInput(inp) => {
-                    let app = app.as_ref().unwrap();
-                    let matches = do_matching(&hints, inp);
-                    app.emit_all("update_results", matches).unwrap();
+                Message::AppHandle(ah) => {
+                    let state: State<Mutex<AppState>> = ah.state();
+                    state.lock().unwrap().space = auto.coord_space();
+                    report_status(&ah, auto.as_mut());
+                    app = Some(ah);
                 }
                 Message::RequestHints => {
-                    elements = auto.get_elements();
-                    hints = create_hints(&elements);
                     let app = app.as_ref().unwrap();
-                    app.trigger_global("go_full", None);
-
-                    std::thread::sleep(Duration::from_millis(100)); //HACK wait for fullscreen before send results
-                    app.emit_all("update_results", hints.iter().collect::<Vec<&Hint>>())
-                        .unwrap();
+                    let start = Instant::now();
+                    let stack = if args.config.hide_covered {
+                        auto.window_stack()
+                    } else {
+                        vec![]
+                    };
+                    let overlay =
+                        Overlay::new(app, &args, auto.get_topmost(), stack, auto.coord_space());
+                    overlay.show(app);
+                    for pid in changes.lock().unwrap().drain() {
+                        cache.invalidate(pid);
+                    }
+                    let key = cache::Key::new(scan_mode, auto.get_topmost().as_ref());
+                    let mut hinter = Hinter::default();
+                    let cached = key.as_ref().and_then(|k| cache.get(k)).cloned();
+                    let from_cache = cached.is_some();
+                    tree = match cached {
+                        //the window is walked again once the overlay is up, which keeps their labels
+                        Some(cached) => cached,
+                        None => {
+                            let (sender, receiver) = std::sync::mpsc::channel();
+                            let streaming = {
+                                let (app, overlay) = (app.clone(), overlay.clone());
+                                std::thread::spawn(move || stream_hints(app, overlay, receiver))
+                            };
+                            //the sender is dropped after the walk, which ends the streaming
+                            let walked = auto.get_elements(&mut Batches::new(sender));
+                            hinter = streaming.join().unwrap_or_default();
+                            match walked {
+                                Ok(tree) => {
+                                    if let Some(key) = key.clone() {
+                                        cache.insert(key, tree.clone());
+                                    }
+                                    if let Some(path) = args.record.as_ref() {
+                                        save_snapshot(auto.as_ref(), &tree, path);
+                                    }
+                                    tree
+                                }
+                                Err(e) => {
+                                    report_error(app, &e);
+                                    ElementTree::default()
+                                }
+                            }
+                        }
+                    };
+                    (elements, hints) = finish_hints(app, &overlay, &tree, &mut hinter, start);
+                    live = Some(Live {
+                        pid: match scan_mode {
+                            ScanMode::Topmost => auto.get_topmost().map(|t| t.pid),
+                            ScanMode::AllWindows => None,
+                        },
+                        overlay,
+                        hinter,
+                        key,
+                        menu: None,
+                        cached: from_cache,
+                    });
+                }
+                Message::Hidden => {
+                    live = None;
                 }
                 Message::Invoke(hid, action) => {
+                    //the backend finds elements in what it walked, which cached ones are not
+                    let stale = live.as_mut().filter(|l| l.cached);
+                    if let (Some(live), Some(app)) = (stale, app.as_ref()) {
+                        refresh_hints(
+                            app,
+                            auto.as_mut(),
+                            live,
+                            &mut cache,
+                            &mut tree,
+                            &mut elements,
+                            &mut hints,
+                        );
+                    }
+                    live = None;
                     println!("searching for {}", hid);
                     if let Some(hindex) = hints.iter().position(|h| h.hint == hid) {
-                        let ele = &elements[hindex];
+                        let mut ele = elements[hindex].clone();
                         println!("Found {}", ele.name);
-                        auto.invoke(ele, action);
+                        let app = app.as_ref().unwrap();
+                        //invoking usually changes the window
+                        cache.invalidate(ele.pid);
+                        //listed while out of view, so bring it into view first
+                        if ele.offscreen {
+                            match auto.scroll_into_view(&ele) {
+                                Ok(scrolled) => ele = scrolled,
+                                //clicking where it was would hit something else
+                                Err(e) => {
+                                    println!("could not scroll {} into view: {e}", ele.name);
+                                    (elements, hints) = (vec![], vec![]);
+                                    set_hints(app, vec![]);
+                                    report_error(app, &e);
+                                    show_window(app.clone());
+                                    continue;
+                                }
+                            }
+                        }
+                        match auto.invoke(&ele, action) {
+                            //menu mode, hint the menu which opened and so on into submenus
+                            Ok(()) if ele.opens_menu => {
+                                let start = Instant::now();
+                                if let Some(menu) = wait_for_menu(auto.as_mut(), &ele) {
+                                    tree = menu;
+                                    let state: State<Mutex<AppState>> = app.state();
+                                    state.lock().unwrap().input.clear();
+                                    app.emit_all("show", ()).unwrap();
+                                    show_window(app.clone());
+                                    //menus are in front of every window
+                                    let overlay = Overlay::new(
+                                        app,
+                                        &args,
+                                        auto.get_topmost(),
+                                        vec![],
+                                        auto.coord_space(),
+                                    );
+                                    overlay.show(app);
+                                    let mut hinter = Hinter::default();
+                                    (elements, hints) =
+                                        finish_hints(app, &overlay, &tree, &mut hinter, start);
+                                    let _ = app.get_window("main").unwrap().set_focus();
+                                    live = Some(Live {
+                                        pid: auto.get_topmost().map(|t| t.pid),
+                                        overlay,
+                                        hinter,
+                                        key: None,
+                                        menu: Some(ele),
+                                        cached: false,
+                                    });
+                                }
+                            }
+                            Ok(()) => {}
+                            Err(e) => {
+                                //the overlay is hidden by now, so bring it back to say why
+                                report_error(app, &e);
+                                show_window(app.clone());
+                            }
+                        }
                     } else {
                         println!(
                             "no hint found for {} in {:?}",
@@ -321,19 +651,440 @@ fn worker(rec: Receiver<Message>, debug: bool, show_taskbar: bool) {
                         );
                     }
                 }
+                Message::CheckStatus => {
+                    report_status(app.as_ref().unwrap(), auto.as_mut());
+                }
+                Message::EnablePrerequisite(id) => {
+                    let app = app.as_ref().unwrap();
+                    if let Err(e) = auto.enable_prerequisite(&id) {
+                        report_error(app, &e);
+                    }
+                    report_status(app, auto.as_mut());
+                }
                 Message::SaveTopmost => {
                     auto.save_topmost();
                 }
+                Message::ToggleScanMode => {
+                    scan_mode = match scan_mode {
+                        ScanMode::Topmost => ScanMode::AllWindows,
+                        ScanMode::AllWindows => ScanMode::Topmost,
+                    };
+                    println!("scan mode {scan_mode:?}");
+                    auto.set_scan_mode(scan_mode);
+                    if let Some(app) = app.as_ref() {
+                        let item = app.tray_handle().get_item("all_windows");
+                        let _ = item.set_selected(scan_mode == ScanMode::AllWindows);
+                    }
+                }
+                Message::SaveSnapshot => {
+                    let secs = SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
+                    let path = std::env::temp_dir().join(format!("shortkey-snapshot-{secs}.json"));
+                    save_snapshot(auto.as_ref(), &tree, &path);
+                }
+            }
+        }
+    }
+}
+
+///menus open asynchronously, so give the popup a moment to appear
+fn wait_for_menu(auto: &mut dyn AccessibilityCalls, from: &UiElement) -> Option<ElementTree> {
+    let start = std::time::Instant::now();
+    while start.elapsed() < Duration::from_millis(1000) {
+        match auto.get_open_menu(from) {
+            Ok(Some(menu)) if menu.nodes.iter().any(|n| n.include) => {
+                println!("menu opened in {}ms", start.elapsed().as_millis());
+                return Some(menu);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                println!("could not get the menu: {e}");
+                return None;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    println!("no menu opened for {}", from.name);
+    None
+}
+
+///where hints go on screen
+#[derive(Clone)]
+struct Overlay {
+    monitors: Vec<Monitor>,
+    ///what the overlay covers, none if we could not get the monitors
+    area: Option<Monitor>,
+    hide_covered: bool,
+    list_offscreen: bool,
+    ///windows frontmost first, in the backend's space
+    stack: Vec<Parent>,
+}
+
+impl Overlay {
+    ///how far apart hints listed in the corner are, in logical pixels
+    const CORNER_STEP: i32 = 40;
+
+    ///target is the window we are hinting, stack the windows in front of one another, in the backend's space
+    fn new(
+        app: &AppHandle,
+        args: &Args,
+        target: Option<Parent>,
+        stack: Vec<Parent>,
+        space: CoordSpace,
+    ) -> Self {
+        let window = app.get_window("main").unwrap();
+        let monitors = monitors(&window, &args.monitors);
+        let target = target.map(|t| monitor::to_physical(&monitors, space, t.rect()));
+        let area = monitor::overlay_area(&monitors, target, args.config.overlay);
+        Overlay {
+            monitors,
+            area,
+            hide_covered: args.config.hide_covered,
+            list_offscreen: args.config.list_offscreen,
+            stack,
+        }
+    }
+
+    fn show(&self, app: &AppHandle) {
+        if let Some(area) = self.area {
+            app.trigger_global("go_full", serde_json::to_string(&area).ok());
+            std::thread::sleep(Duration::from_millis(100)); //HACK wait for fullscreen before send results
+        }
+    }
+
+    ///hint the elements which are on the overlay, returns them along with their hints.
+    ///elements which are one target get one hint
+    fn place(&self, elements: Vec<UiElement>, hinter: &mut Hinter) -> (Vec<UiElement>, Vec<Hint>) {
+        let mut placed = vec![];
+        let mut hints = vec![];
+        let mut covered = 0;
+        let mut offscreen = vec![];
+        let mut cornered = 0;
+        for mut e in dedup::dedup(elements) {
+            let hidden = match e.offscreen {
+                false => occlusion::hidden(&e, &self.stack),
+                true => None,
+            };
+            match hidden {
+                Some(Hidden::Scrolled) if self.list_offscreen => e.offscreen = true,
+                Some(_) if self.hide_covered => {
+                    covered += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            //those out of view are on the monitor of the list they are in
+            let shown = match e.offscreen {
+                true => e.clip.unwrap_or(e.rect()),
+                false => e.rect(),
+            };
+            let mut rect = monitor::to_physical(&self.monitors, e.space, shown);
+            //nothing to point at e.g. tray icons no panel shows, so list them in the corner,
+            //side by side so their hints do not cover one another
+            if rect.width == 0 && rect.height == 0 {
+                if let Some(area) = self.area {
+                    let step = (Self::CORNER_STEP as f64 * area.scale) as i32;
+                    let across = (area.bounds.width / step).max(1);
+                    rect.x = area.bounds.x + cornered % across * step;
+                    rect.y = area.bounds.y + cornered / across * step;
+                    cornered += 1;
+                }
+            }
+            let (x, y) = rect.centre();
+            //elements on other monitors would be hinted off the overlay
+            if !self.area.is_none_or(|a| a.bounds.contains(x, y)) {
+                continue;
+            }
+            if e.offscreen {
+                offscreen.push(e);
+                continue;
+            }
+            let mut hint = match hinter.hint(&e) {
+                Ok(hint) => hint,
+                Err(err) => {
+                    println!("{err}");
+                    continue;
+                }
+            };
+            if let Some(area) = self.area {
+                hint.place(area.to_overlay(rect));
+            }
+            hints.push(hint);
+            placed.push(e);
+        }
+        //only listed, after those on screen so they get the short labels
+        for e in offscreen {
+            match hinter.hint(&e) {
+                Ok(hint) => {
+                    hints.push(hint);
+                    placed.push(e);
+                }
+                Err(err) => println!("{err}"),
             }
         }
+        if covered > 0 {
+            println!("skipped {covered} covered elements");
+        }
+        (placed, hints)
+    }
+}
+
+///the open overlay, whose hints are kept up to date as the application changes
+struct Live {
+    overlay: Overlay,
+    ///keeps the labels of elements which are still there
+    hinter: Hinter,
+    ///the application hinted, none for all windows
+    pid: Option<i32>,
+    key: Option<cache::Key>,
+    ///the element which opened the menu being hinted
+    menu: Option<UiElement>,
+    ///showing what was cached, which the backend has not walked yet
+    cached: bool,
+}
+
+impl Live {
+    ///how often to check for changes, they often come in bursts
+    const INTERVAL: Duration = Duration::from_millis(300);
+}
+
+///walk what is hinted again, and send the hints which changed
+fn refresh_hints(
+    app: &AppHandle,
+    auto: &mut dyn AccessibilityCalls,
+    live: &mut Live,
+    cache: &mut Cache,
+    tree: &mut ElementTree,
+    elements: &mut Vec<UiElement>,
+    hints: &mut Vec<Hint>,
+) {
+    if let Some((placed, new_hints)) = rewalk(auto, live, cache, tree) {
+        update_hints(app, hints, new_hints.clone());
+        *elements = placed;
+        *hints = new_hints;
+    }
+}
+
+///the hinted elements walked again, labelled as they were. none if nothing could be walked
+fn rewalk(
+    auto: &mut dyn AccessibilityCalls,
+    live: &mut Live,
+    cache: &mut Cache,
+    tree: &mut ElementTree,
+) -> Option<(Vec<UiElement>, Vec<Hint>)> {
+    let walked = match (live.menu.as_ref(), live.cached) {
+        (Some(from), _) => auto.get_open_menu(from),
+        (None, true) => auto.get_elements(&mut Batches::none()).map(Some),
+        (None, false) => auto.refresh().map(Some),
+    };
+    live.cached = false;
+    match walked {
+        Ok(Some(walked)) => {
+            *tree = walked;
+            if let Some(key) = live.key.clone() {
+                cache.insert(key, tree.clone());
+            }
+            let (placed, hints) = live.overlay.place(tree.flatten(), &mut live.hinter);
+            live.hinter.keep(&hints);
+            Some((placed, hints))
+        }
+        //the menu closed
+        Ok(None) => None,
+        Err(e) => {
+            println!("could not refresh hints: {e}");
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct HintsChanged<'a> {
+    ///only those matching the input
+    added: Vec<&'a Hint>,
+    ///labels of hints which are gone
+    removed: Vec<String>,
+    ///moved, resized or renamed
+    moved: Vec<&'a Hint>,
+}
+
+impl<'a> HintsChanged<'a> {
+    ///what changed between the hints, by label. those added are narrowed down by the input
+    fn between(old: &[Hint], new: &'a [Hint], input: &str) -> Self {
+        let before: HashMap<&str, &Hint> = old.iter().map(|h| (h.hint.as_str(), h)).collect();
+        let after: HashSet<&str> = new.iter().map(|h| h.hint.as_str()).collect();
+        HintsChanged {
+            added: do_matching(new, input.to_string())
+                .into_iter()
+                .filter(|h| !before.contains_key(h.hint.as_str()))
+                .to_vec(),
+            removed: old
+                .iter()
+                .filter(|h| !after.contains(h.hint.as_str()))
+                .map(|h| h.hint.clone())
+                .to_vec(),
+            moved: new
+                .iter()
+                .filter(|h| before.get(h.hint.as_str()).is_some_and(|b| b != h))
+                .to_vec(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty()
+    }
+}
+
+///replace the hints, sending only what changed
+fn update_hints(app: &AppHandle, old: &[Hint], new: Vec<Hint>) {
+    let state: State<Mutex<AppState>> = app.state();
+    let mut state = state.lock().unwrap();
+    let changes = HintsChanged::between(old, &new, &state.input);
+    if !changes.is_empty() {
+        println!(
+            "hints changed: {} added, {} removed, {} moved",
+            changes.added.len(),
+            changes.removed.len(),
+            changes.moved.len()
+        );
+        if let Err(e) = app.emit_all("hints_changed", changes) {
+            eprintln!("error sending changed hints: {:?}", e);
+        }
+    }
+    state.hints = new;
+}
+
+///hint each batch as it is walked, so they show before the walk is done.
+///returns the hinter, so the final hints keep their labels
+fn stream_hints(app: AppHandle, overlay: Overlay, batches: Receiver<Vec<UiElement>>) -> Hinter {
+    let mut hinter = Hinter::default();
+    let mut elements = vec![];
+    for batch in batches {
+        //all of them again, as a batch can have the label of a button in the one before
+        elements.extend(batch);
+        let (_, hints) = overlay.place(elements.clone(), &mut hinter);
+        set_hints(&app, hints);
+    }
+    hinter
+}
+
+///hint the whole tree and say we are done, returns the elements which got a hint
+fn finish_hints(
+    app: &AppHandle,
+    overlay: &Overlay,
+    tree: &ElementTree,
+    hinter: &mut Hinter,
+    start: Instant,
+) -> (Vec<UiElement>, Vec<Hint>) {
+    let (elements, hints) = overlay.place(tree.flatten(), hinter);
+    set_hints(app, hints.clone());
+    let event = CompleteEvent {
+        hinted: hints.len(),
+        walked: tree.nodes.len(),
+        millis: start.elapsed().as_millis(),
+        truncated: tree.truncated,
+    };
+    println!("{event:?}");
+    if let Err(e) = app.emit_all("complete", event) {
+        eprintln!("error sending complete event: {:?}", e);
+    }
+    (elements, hints)
+}
+
+///replace the hints, and send the ones matching the input
+fn set_hints(app: &AppHandle, hints: Vec<Hint>) {
+    let state: State<Mutex<AppState>> = app.state();
+    let mut state = state.lock().unwrap();
+    state.hints = hints;
+    emit_results(app, &state);
+}
+
+fn emit_results(app: &AppHandle, state: &AppState) {
+    let matches = do_matching(&state.hints, state.input.clone());
+    if let Err(e) = app.emit_all("update_results", matches) {
+        eprintln!("error sending results: {:?}", e);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CompleteEvent {
+    ///elements with a hint
+    hinted: usize,
+    ///every node walked, containers too
+    walked: usize,
+    millis: u128,
+    ///a walk budget ran out, so there may be more elements
+    truncated: Option<Truncation>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct StatusEvent {
+    ///every prerequisite is met
+    ok: bool,
+    prerequisites: Vec<Prerequisite>,
+}
+
+///check what we need from the os and tell the frontend
+fn report_status(app: &AppHandle, auto: &mut dyn AccessibilityCalls) {
+    let prerequisites = auto.check_prerequisites();
+    for p in prerequisites.iter().filter(|p| !p.ok) {
+        println!("missing prerequisite {}: {}", p.id, p.hint);
+    }
+    let event = StatusEvent {
+        ok: prerequisites.iter().all(|p| p.ok),
+        prerequisites,
+    };
+    if let Err(e) = app.emit_all("status", event) {
+        eprintln!("error sending status event: {:?}", e);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ErrorEvent {
+    kind: String,
+    message: String,
+}
+
+///tell the frontend why something did not work
+fn report_error(app: &AppHandle, error: &AccessError) {
+    println!("error: {error}");
+    let event = ErrorEvent {
+        kind: error.kind().to_string(),
+        message: error.to_string(),
+    };
+    if let Err(e) = app.emit_all("error", event) {
+        eprintln!("error sending error event: {:?}", e);
+    }
+}
+
+///the last scan, with the window it came from
+fn save_snapshot(auto: &dyn AccessibilityCalls, tree: &ElementTree, path: &Path) {
+    let snapshot = Snapshot::new(auto.get_topmost(), tree.clone());
+    if let Err(e) = snapshot.save(path) {
+        println!("could not save snapshot to {path:?}: {e}");
     }
 }
 
-fn get_accessibility(debug: bool, show_taskbar: bool) -> impl AccessibilityCalls {
+fn get_accessibility(args: &Args) -> Box<dyn AccessibilityCalls> {
+    if let Some(path) = args.replay.as_ref() {
+        match Snapshot::load(path) {
+            Ok(snapshot) => return Box::new(fixture::FixtureBackend::from_snapshot(snapshot)),
+            Err(e) => println!("could not load snapshot {path:?}: {e}"),
+        }
+    }
+    if let Some(path) = args.fixture.as_ref() {
+        match fixture::FixtureBackend::load(path) {
+            Ok(fixture) => return Box::new(fixture),
+            Err(e) => println!("could not load fixture {path:?}: {e}"),
+        }
+    }
     #[cfg(target_os = "macos")]
-    return mac::Osx::new();
+    return Box::new(mac::Osx::new(args.config.clone()));
     #[cfg(target_os = "windows")]
-    return windows::Windows::new(debug, show_taskbar);
+    return Box::new(windows::Windows::new(args.debug, args.config.clone()));
+    #[cfg(target_os = "linux")]
+    return Box::new(linux::Linux::new(args.debug, args.config.clone()));
 }
 
 ///exact hints first, then fuzzy
@@ -385,7 +1136,7 @@ fn hide_window(app: AppHandle) {
     #[cfg(not(target_os = "macos"))]
     app.get_window("main").unwrap().hide().unwrap();
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct Hint {
     text: String,
     hint: String,
@@ -397,6 +1148,24 @@ struct Hint {
     height: i32,
     control: String,
     parent: String,
+    group: String,
+    editable: bool,
+    window: String,
+    ///the element's space, logical once placed on the overlay
+    space: CoordSpace,
+    ///scrolled out of view, so it is only listed
+    offscreen: bool,
+}
+
+impl Hint {
+    ///css pixels in the overlay
+    fn place(&mut self, rect: Rect) {
+        self.x = rect.x;
+        self.y = rect.y;
+        self.width = rect.width;
+        self.height = rect.height;
+        self.space = CoordSpace::Logical;
+    }
 }
 
 impl From<&UiElement> for Hint {
@@ -412,6 +1181,267 @@ impl From<&UiElement> for Hint {
             height: e.height,
             control: e.control.clone(),
             parent: e.parent.clone(),
+            group: e.group.clone(),
+            editable: e.editable,
+            window: e.window.clone(),
+            space: e.space,
+            offscreen: e.offscreen,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixture::FixtureBackend;
+
+    fn calculator() -> FixtureBackend {
+        FixtureBackend::load(Path::new("fixtures/calculator.json")).unwrap()
+    }
+
+    ///hint every element, as the overlay does in walk order
+    fn hint_all(auto: &mut FixtureBackend) -> (Vec<UiElement>, Vec<Hint>) {
+        let elements = auto.get_elements(&mut Batches::none()).unwrap().flatten();
+        let mut hinter = Hinter::default();
+        let hints = elements.iter().map(|e| hinter.hint(e).unwrap()).to_vec();
+        (elements, hints)
+    }
+
+    ///what choosing the first match does
+    fn choose(auto: &FixtureBackend, elements: &[UiElement], hints: &[Hint], input: &str) {
+        let chosen = do_matching(hints, input.to_string())[0];
+        let index = hints.iter().position(|h| h.hint == chosen.hint).unwrap();
+        auto.invoke(&elements[index], Action::LeftClick).unwrap();
+    }
+
+    #[test]
+    fn labels_come_from_names() {
+        let mut auto = calculator();
+        let (_, hints) = hint_all(&mut auto);
+        let labels = hints.iter().map(|h| h.hint.as_str()).to_vec();
+        assert_eq!(labels, ["C", "D", "S", "E", "N", "A", "B", "F"]);
+    }
+
+    #[test]
+    fn typing_a_name_invokes_that_element() {
+        let mut auto = calculator();
+        let (elements, hints) = hint_all(&mut auto);
+        choose(&auto, &elements, &hints, "seven");
+        let invoked = auto.invoked();
+        assert_eq!(invoked.len(), 1);
+        assert_eq!(invoked[0].0.name, "Seven");
+        assert_eq!(invoked[0].1, Action::LeftClick);
+    }
+
+    #[test]
+    fn typing_a_label_invokes_its_element_before_fuzzy_matches() {
+        let mut auto = calculator();
+        let (elements, hints) = hint_all(&mut auto);
+        //equals and scientific have an e in them too
+        choose(&auto, &elements, &hints, "e");
+        choose(&auto, &elements, &hints, "a");
+        let names = auto.invoked().into_iter().map(|(e, _)| e.name).to_vec();
+        assert_eq!(names, ["Eight", "Equals"]);
+    }
+
+    fn button(id: &str, name: &str, x: i32) -> UiElement {
+        UiElement {
+            id: id.into(),
+            name: name.into(),
+            control: "Button".into(),
+            x,
+            y: 10,
+            width: 40,
+            height: 20,
+            ..Default::default()
+        }
+    }
+
+    fn labels(hints: &[Hint]) -> Vec<(&str, &str)> {
+        hints
+            .iter()
+            .map(|h| (h.text.as_str(), h.hint.as_str()))
+            .to_vec()
+    }
+
+    #[test]
+    fn hints_keep_their_labels_across_a_refresh() {
+        let mut hinter = Hinter::default();
+        let before = [button("1", "Save", 0), button("2", "Open", 50)]
+            .iter()
+            .map(|e| hinter.hint(e).unwrap())
+            .to_vec();
+        assert_eq!(labels(&before), [("Save", "S"), ("Open", "O")]);
+        //a new element walked before them, which would have taken the S
+        let after = [
+            button("3", "Search", 0),
+            button("2", "Open", 60),
+            button("1", "Save", 100),
+        ]
+        .iter()
+        .map(|e| hinter.hint(e).unwrap())
+        .to_vec();
+        assert_eq!(
+            labels(&after),
+            [("Search", "A"), ("Open", "O"), ("Save", "S")]
+        );
+    }
+
+    #[test]
+    fn labels_of_removed_hints_are_given_out_again() {
+        let mut hinter = Hinter::default();
+        let save = hinter.hint(&button("1", "Save", 0)).unwrap();
+        let open = hinter.hint(&button("2", "Open", 50)).unwrap();
+        hinter.keep(&[open]);
+        //the same element again gets a label like a new one, which is free again
+        let search = hinter.hint(&button("3", "Search", 0)).unwrap();
+        assert_eq!(search.hint, save.hint);
+        assert_eq!(hinter.hint(&button("2", "Open", 60)).unwrap().hint, "O");
+    }
+
+    #[test]
+    fn running_out_of_labels_is_an_error() {
+        let letters = ('A'..='Z').map(|c| c.to_string()).to_vec();
+        let mut hinter = Hinter::default();
+        for a in letters.iter() {
+            hinter.used.insert(a.clone());
+            for b in letters.iter() {
+                hinter.used.insert(format!("{a}{b}"));
+                for c in letters.iter() {
+                    hinter.used.insert(format!("{a}{b}{c}"));
+                }
+            }
+        }
+        assert!(hinter.hint(&button("1", "Save", 0)).is_err());
+    }
+
+    #[test]
+    fn only_what_changed_is_sent() {
+        let mut hinter = Hinter::default();
+        let old = [button("1", "Save", 0), button("2", "Open", 50)]
+            .iter()
+            .map(|e| hinter.hint(e).unwrap())
+            .to_vec();
+        let new = [button("2", "Open", 60), button("3", "Close", 100)]
+            .iter()
+            .map(|e| hinter.hint(e).unwrap())
+            .to_vec();
+        let changes = HintsChanged::between(&old, &new, "");
+        assert_eq!(
+            labels(&changes.added.into_iter().cloned().to_vec()),
+            [("Close", "C")]
+        );
+        assert_eq!(changes.removed, ["S"]);
+        assert_eq!(
+            labels(&changes.moved.into_iter().cloned().to_vec()),
+            [("Open", "O")]
+        );
+        assert!(HintsChanged::between(&new, &new, "").is_empty());
+    }
+
+    #[test]
+    fn hints_added_are_narrowed_down_by_the_input() {
+        let mut hinter = Hinter::default();
+        let new = [button("1", "Save", 0), button("2", "Open", 50)]
+            .iter()
+            .map(|e| hinter.hint(e).unwrap())
+            .to_vec();
+        let changes = HintsChanged::between(&[], &new, "open");
+        assert_eq!(
+            labels(&changes.added.into_iter().cloned().to_vec()),
+            [("Open", "O")]
+        );
+    }
+
+    #[test]
+    fn cached_hints_are_walked_again_before_invoking() {
+        let mut auto = calculator();
+        //seven moved since it was cached, too far to be found where it was
+        let mut cached = auto.get_elements(&mut Batches::none()).unwrap();
+        for node in cached
+            .nodes
+            .iter_mut()
+            .filter(|n| n.element.name == "Seven")
+        {
+            node.element.x += 500;
+        }
+        let mut hinter = Hinter::default();
+        let stale = cached.flatten();
+        let labels = stale.iter().map(|e| hinter.hint(e).unwrap()).to_vec();
+        let seven = labels.iter().position(|h| h.text == "Seven").unwrap();
+        assert!(auto.invoke(&stale[seven], Action::LeftClick).is_err());
+
+        let mut live = Live {
+            overlay: Overlay {
+                monitors: vec![],
+                area: None,
+                hide_covered: false,
+                list_offscreen: true,
+                stack: vec![],
+            },
+            hinter,
+            pid: None,
+            key: None,
+            menu: None,
+            cached: true,
+        };
+        let mut cache = Cache::new(Duration::from_secs(60));
+        let (elements, hints) = rewalk(&mut auto, &mut live, &mut cache, &mut cached).unwrap();
+        assert!(!live.cached);
+        //what did not move keeps its label, and seven is invoked where it is now
+        let label = |hints: &[Hint], text: &str| {
+            hints.iter().find(|h| h.text == text).unwrap().hint.clone()
+        };
+        assert_eq!(label(&hints, "Clear"), label(&labels, "Clear"));
+        let seven = hints.iter().position(|h| h.text == "Seven").unwrap();
+        auto.invoke(&elements[seven], Action::LeftClick).unwrap();
+        assert_eq!(auto.invoked()[0].0.name, "Seven");
+    }
+
+    #[test]
+    fn elements_with_nowhere_to_point_are_listed_side_by_side() {
+        let screen = Monitor {
+            bounds: Rect {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            },
+            scale: 1.5,
+        };
+        let overlay = Overlay {
+            monitors: vec![screen],
+            area: Some(screen),
+            hide_covered: false,
+            list_offscreen: true,
+            stack: vec![],
+        };
+        let tray = ["Volume", "Network", "Battery"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| UiElement {
+                width: 0,
+                height: 0,
+                ..button(&i.to_string(), name, 0)
+            })
+            .to_vec();
+        let (_, hints) = overlay.place(tray, &mut Hinter::default());
+        let spots = hints.iter().map(|h| (h.x, h.y)).to_vec();
+        assert_eq!(spots, [(0, 0), (40, 0), (80, 0)]);
+    }
+
+    #[test]
+    fn invoking_an_element_which_went_away_fails() {
+        let auto = calculator();
+        let gone = UiElement {
+            name: "Percent".into(),
+            control: "Button".into(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            auto.invoke(&gone, Action::LeftClick),
+            Err(AccessError::Stale(_))
+        ));
+        assert!(auto.invoked().is_empty());
+    }
+}
@@ -0,0 +1,379 @@
+use std::{error::Error, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::OverlayMode, traits::CoordSpace};
+
+///physical pixels in desktop coordinates, where the primary monitor starts at 0,0
+///and others can be negative e.g. left of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    pub fn centre(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    ///the part in both, empty if they do not overlap
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Rect {
+            x,
+            y,
+            width: ((self.x + self.width).min(other.x + other.width) - x).max(0),
+            height: ((self.y + self.height).min(other.y + other.height) - y).max(0),
+        }
+    }
+
+    ///smallest rect covering all of them
+    pub fn union(rects: &[Rect]) -> Option<Rect> {
+        let left = rects.iter().map(|r| r.x).min()?;
+        let top = rects.iter().map(|r| r.y).min()?;
+        let right = rects.iter().map(|r| r.x + r.width).max()?;
+        let bottom = rects.iter().map(|r| r.y + r.height).max()?;
+        Some(Rect {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+
+    fn scaled(&self, by: f64) -> Rect {
+        let scale = |v: i32| (v as f64 * by).round() as i32;
+        Rect {
+            x: scale(self.x),
+            y: scale(self.y),
+            width: scale(self.width),
+            height: scale(self.height),
+        }
+    }
+
+    ///how far the point is outside, 0 if inside
+    fn distance(&self, x: i32, y: i32) -> i64 {
+        let dx = (self.x - x).max(x - (self.x + self.width - 1)).max(0) as i64;
+        let dy = (self.y - y).max(y - (self.y + self.height - 1)).max(0) as i64;
+        dx * dx + dy * dy
+    }
+}
+
+///a monitor, or the overlay covering some of them
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Monitor {
+    ///in physical pixels
+    #[serde(flatten)]
+    pub bounds: Rect,
+    ///physical pixels per logical pixel e.g. 1.5 at 150%
+    #[serde(default = "unscaled")]
+    pub scale: f64,
+}
+
+fn unscaled() -> f64 {
+    1.0
+}
+
+impl Monitor {
+    ///bounds in logical pixels, the position is scaled too like tauri does
+    fn logical(&self) -> Rect {
+        self.bounds.scaled(1.0 / self.scale)
+    }
+
+    ///physical desktop pixels to css pixels in a webview covering this
+    pub fn to_overlay(self, rect: Rect) -> Rect {
+        Rect {
+            x: rect.x - self.bounds.x,
+            y: rect.y - self.bounds.y,
+            ..rect
+        }
+        .scaled(1.0 / self.scale)
+    }
+}
+
+///the monitor the point is on, else the closest one e.g. for a window hanging off the edge
+pub fn monitor_at(monitors: &[Monitor], x: i32, y: i32) -> Option<Monitor> {
+    monitors
+        .iter()
+        .min_by_key(|m| m.bounds.distance(x, y))
+        .copied()
+}
+
+///where the overlay goes, target is the window we are hinting.
+///spanning monitors with different scales, the webview gets the scale of the middle one
+pub fn overlay_area(
+    monitors: &[Monitor],
+    target: Option<Rect>,
+    mode: OverlayMode,
+) -> Option<Monitor> {
+    match mode {
+        OverlayMode::AllMonitors => {
+            let bounds = Rect::union(&monitors.iter().map(|m| m.bounds).collect::<Vec<_>>())?;
+            let (x, y) = bounds.centre();
+            let scale = monitor_at(monitors, x, y)?.scale;
+            Some(Monitor { bounds, scale })
+        }
+        OverlayMode::TargetMonitor => {
+            let (x, y) = target.map(|t| t.centre()).unwrap_or_default();
+            monitor_at(monitors, x, y)
+        }
+    }
+}
+
+///element bounds in physical desktop pixels
+pub fn to_physical(monitors: &[Monitor], space: CoordSpace, rect: Rect) -> Rect {
+    match space {
+        CoordSpace::Physical => rect,
+        CoordSpace::Logical => {
+            let scale = monitors
+                .iter()
+                .find(|m| m.logical().contains(rect.x, rect.y))
+                .map(|m| m.scale)
+                .unwrap_or(1.0);
+            rect.scaled(scale)
+        }
+    }
+}
+
+///a made up monitor layout, instead of the real one
+pub fn load(path: &Path) -> Result<Vec<Monitor>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let monitors: Vec<Monitor> = serde_json::from_str(&text)?;
+    println!("loaded {} monitors from {path:?}", monitors.len());
+    Ok(monitors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(x: i32, y: i32, width: i32, height: i32, scale: f64) -> Monitor {
+        Monitor {
+            bounds: Rect {
+                x,
+                y,
+                width,
+                height,
+            },
+            scale,
+        }
+    }
+
+    ///primary with a bigger one to the left of it, so left of the origin
+    fn side_by_side() -> Vec<Monitor> {
+        vec![
+            monitor(0, 0, 1920, 1080, 1.0),
+            monitor(-2560, 0, 2560, 1440, 1.0),
+        ]
+    }
+
+    #[test]
+    fn monitor_at_finds_the_monitor_under_the_point() {
+        let monitors = side_by_side();
+        assert_eq!(monitor_at(&monitors, 100, 100), Some(monitors[0]));
+        assert_eq!(monitor_at(&monitors, -1, 0), Some(monitors[1]));
+        assert_eq!(monitor_at(&monitors, -2560, 1439), Some(monitors[1]));
+    }
+
+    #[test]
+    fn monitor_at_falls_back_to_the_closest() {
+        let monitors = side_by_side();
+        //below the primary, which is shorter than the left one
+        assert_eq!(monitor_at(&monitors, 500, 1300), Some(monitors[0]));
+        assert_eq!(monitor_at(&monitors, -3000, -50), Some(monitors[1]));
+        assert_eq!(monitor_at(&[], 0, 0), None);
+    }
+
+    #[test]
+    fn overlay_spans_all_monitors() {
+        let monitors = side_by_side();
+        let area = overlay_area(&monitors, None, OverlayMode::AllMonitors).unwrap();
+        assert_eq!(
+            area.bounds,
+            Rect {
+                x: -2560,
+                y: 0,
+                width: 4480,
+                height: 1440
+            }
+        );
+        assert_eq!(overlay_area(&[], None, OverlayMode::AllMonitors), None);
+    }
+
+    #[test]
+    fn overlay_spanning_takes_the_scale_of_the_middle() {
+        let monitors = vec![
+            monitor(0, 0, 1920, 1080, 1.0),
+            monitor(-3840, 0, 3840, 2160, 2.0),
+        ];
+        let area = overlay_area(&monitors, None, OverlayMode::AllMonitors).unwrap();
+        assert_eq!(area.scale, 2.0);
+    }
+
+    #[test]
+    fn overlay_on_the_target_monitor() {
+        let monitors = side_by_side();
+        let target = Rect {
+            x: -1000,
+            y: 200,
+            width: 400,
+            height: 300,
+        };
+        assert_eq!(
+            overlay_area(&monitors, Some(target), OverlayMode::TargetMonitor),
+            Some(monitors[1])
+        );
+        //no target window, so the primary
+        assert_eq!(
+            overlay_area(&monitors, None, OverlayMode::TargetMonitor),
+            Some(monitors[0])
+        );
+    }
+
+    #[test]
+    fn overlay_coordinates_are_relative_to_the_monitor() {
+        let monitors = side_by_side();
+        let rect = Rect {
+            x: -2500,
+            y: 100,
+            width: 50,
+            height: 20,
+        };
+        assert_eq!(
+            monitors[1].to_overlay(rect),
+            Rect {
+                x: 60,
+                y: 100,
+                width: 50,
+                height: 20
+            }
+        );
+        let rect = Rect {
+            x: 10,
+            y: 20,
+            width: 50,
+            height: 20,
+        };
+        assert_eq!(monitors[0].to_overlay(rect), rect);
+    }
+
+    #[test]
+    fn overlay_coordinates_on_a_monitor_above() {
+        let above = monitor(200, -1080, 1920, 1080, 1.0);
+        let rect = Rect {
+            x: 300,
+            y: -1000,
+            width: 10,
+            height: 10,
+        };
+        assert_eq!(
+            above.to_overlay(rect),
+            Rect {
+                x: 100,
+                y: 80,
+                width: 10,
+                height: 10
+            }
+        );
+    }
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    ///primary at 125% with one at 150% left of it, which do not overlap in logical pixels either
+    fn mixed_scales() -> Vec<Monitor> {
+        vec![
+            monitor(0, 0, 2560, 1440, 1.25),
+            monitor(-2880, 0, 2880, 1620, 1.5),
+        ]
+    }
+
+    #[test]
+    fn scaling_rounds_halves_away_from_zero() {
+        assert_eq!(rect(1, 3, 5, 7).scaled(1.5), rect(2, 5, 8, 11));
+        assert_eq!(rect(-3, -1, 2, 2).scaled(1.5), rect(-5, -2, 3, 3));
+        assert_eq!(rect(4, 8, 12, 16).scaled(1.25), rect(5, 10, 15, 20));
+        assert_eq!(rect(5, 10, 15, 20).scaled(1.0 / 1.25), rect(4, 8, 12, 16));
+    }
+
+    #[test]
+    fn physical_bounds_are_left_alone() {
+        let monitors = mixed_scales();
+        let bounds = rect(-100, 50, 30, 30);
+        assert_eq!(to_physical(&monitors, CoordSpace::Physical, bounds), bounds);
+    }
+
+    #[test]
+    fn logical_bounds_take_the_scale_of_their_monitor() {
+        let monitors = mixed_scales();
+        assert_eq!(
+            to_physical(&monitors, CoordSpace::Logical, rect(100, 200, 80, 40)),
+            rect(125, 250, 100, 50)
+        );
+        assert_eq!(
+            to_physical(&monitors, CoordSpace::Logical, rect(-1920, 0, 100, 30)),
+            rect(-2880, 0, 150, 45)
+        );
+        //off every monitor
+        assert_eq!(
+            to_physical(&monitors, CoordSpace::Logical, rect(5000, 0, 10, 10)),
+            rect(5000, 0, 10, 10)
+        );
+    }
+
+    #[test]
+    fn logical_bounds_at_monitor_edges() {
+        let monitors = mixed_scales();
+        //last logical column of the primary stays on it
+        let right = to_physical(&monitors, CoordSpace::Logical, rect(2047, 1151, 1, 1));
+        assert_eq!(right, rect(2559, 1439, 1, 1));
+        assert!(monitors[0].bounds.contains(right.x, right.y));
+        //first column left of the origin is on the other monitor
+        let left = to_physical(&monitors, CoordSpace::Logical, rect(-1, 0, 1, 1));
+        assert_eq!(left, rect(-2, 0, 2, 2));
+        assert!(monitors[1].bounds.contains(left.x, left.y));
+    }
+
+    #[test]
+    fn overlay_coordinates_with_fractional_scales() {
+        let monitors = mixed_scales();
+        assert_eq!(
+            monitors[0].to_overlay(rect(125, 250, 100, 50)),
+            rect(100, 200, 80, 40)
+        );
+        assert_eq!(
+            monitors[1].to_overlay(rect(-2880, 0, 150, 45)),
+            rect(0, 0, 100, 30)
+        );
+    }
+
+    #[test]
+    fn overlay_coordinates_stay_inside_at_monitor_edges() {
+        let monitors = mixed_scales();
+        for m in &monitors {
+            let logical = m.logical();
+            let b = m.bounds;
+            let corner = m.to_overlay(rect(b.x + b.width - 1, b.y + b.height - 1, 1, 1));
+            assert!(corner.x < logical.width && corner.y < logical.height);
+            assert_eq!(m.to_overlay(rect(b.x, b.y, 1, 1)), rect(0, 0, 1, 1));
+        }
+        assert_eq!(
+            monitors[1].to_overlay(rect(-1, 0, 3, 3)),
+            rect(1919, 0, 2, 2)
+        );
+    }
+}
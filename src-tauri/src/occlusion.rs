@@ -0,0 +1,134 @@
+use crate::traits::{Parent, UiElement};
+
+///why clicking the centre of an element would hit something else
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hidden {
+    ///outside its containers e.g. scrolled out of a list
+    Scrolled,
+    ///another window is in front of it, which can be a dialog of the same application
+    Covered,
+}
+
+///stack is the windows frontmost first, in the same space as the element
+pub fn hidden(element: &UiElement, stack: &[Parent]) -> Option<Hidden> {
+    //nothing to click e.g. tray icons
+    if element.width <= 0 || element.height <= 0 {
+        return None;
+    }
+    let (x, y) = element.rect().centre();
+    if element.clip.is_some_and(|c| !c.contains(x, y)) {
+        return Some(Hidden::Scrolled);
+    }
+    //its window is not listed e.g. a panel or popup, so we cannot tell what is in front of it
+    if !stack.iter().any(|w| is_window_of(w, element)) {
+        return None;
+    }
+    stack
+        .iter()
+        .find(|w| w.rect().contains(x, y))
+        .filter(|w| !is_window_of(w, element))
+        .map(|_| Hidden::Covered)
+}
+
+///by id, else by title, else by application when the backend gives neither
+fn is_window_of(window: &Parent, element: &UiElement) -> bool {
+    if !window.window_id.is_empty() && !element.window_id.is_empty() {
+        return window.window_id == element.window_id;
+    }
+    if !window.title.is_empty() && !element.window.is_empty() {
+        return window.pid == element.pid && window.title == element.window;
+    }
+    window.pid == element.pid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::Rect;
+
+    fn window(pid: i32, title: &str, x: i32, y: i32, width: i32, height: i32) -> Parent {
+        Parent {
+            pid,
+            title: title.into(),
+            x,
+            y,
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    fn button(pid: i32, window: &str, x: i32, y: i32) -> UiElement {
+        UiElement {
+            name: "OK".into(),
+            pid,
+            window: window.into(),
+            x,
+            y,
+            width: 20,
+            height: 20,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scrolled_out_of_its_list() {
+        let mut element = button(1, "Editor", 10, 500);
+        element.clip = Some(Rect {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 400,
+        });
+        assert_eq!(hidden(&element, &[]), Some(Hidden::Scrolled));
+        element.y = 100;
+        assert_eq!(hidden(&element, &[]), None);
+    }
+
+    #[test]
+    fn covered_by_another_application() {
+        let stack = [
+            window(2, "Terminal", 0, 0, 300, 300),
+            window(1, "Editor", 0, 0, 800, 600),
+        ];
+        assert_eq!(
+            hidden(&button(1, "Editor", 100, 100), &stack),
+            Some(Hidden::Covered)
+        );
+        assert_eq!(hidden(&button(1, "Editor", 400, 100), &stack), None);
+    }
+
+    #[test]
+    fn covered_by_a_dialog_of_the_same_application() {
+        let stack = [
+            window(1, "Save as", 100, 100, 300, 200),
+            window(1, "Editor", 0, 0, 800, 600),
+        ];
+        assert_eq!(
+            hidden(&button(1, "Editor", 150, 150), &stack),
+            Some(Hidden::Covered)
+        );
+        assert_eq!(hidden(&button(1, "Save as", 150, 150), &stack), None);
+    }
+
+    #[test]
+    fn windows_are_matched_by_id_first() {
+        let mut front = window(1, "Editor", 0, 0, 300, 300);
+        front.window_id = "a".into();
+        let mut back = window(1, "Editor", 0, 0, 800, 600);
+        back.window_id = "b".into();
+        let mut element = button(1, "Editor", 100, 100);
+        element.window_id = "b".into();
+        assert_eq!(hidden(&element, &[front, back]), Some(Hidden::Covered));
+    }
+
+    #[test]
+    fn unknown_windows_are_not_judged() {
+        //e.g. a panel, which is not in the stacking order
+        let stack = [window(2, "Terminal", 0, 0, 300, 300)];
+        assert_eq!(hidden(&button(1, "panel", 100, 100), &stack), None);
+        let mut tray = button(1, "tray", 0, 0);
+        tray.width = 0;
+        assert_eq!(hidden(&tray, &stack), None);
+    }
+}
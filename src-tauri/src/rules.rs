@@ -0,0 +1,452 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::traits::UiElement;
+
+///the states rules can match on. linux and windows have all of them, mac has no focusable
+pub const STATES: &[&str] = &[
+    "enabled",
+    "focusable",
+    "focused",
+    "selected",
+    "checked",
+    "expanded",
+];
+
+///decides whether elements get hints and whether their children are walked.
+///it matches when all of its conditions do, missing conditions match anything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rule {
+    ///application name or pid
+    pub app: Option<String>,
+    ///controls by the name the backend gives them e.g. PushButton on linux, Button on windows
+    pub roles: Vec<String>,
+    ///regex on the element's name
+    pub name: Option<String>,
+    pub min_width: Option<i32>,
+    pub max_width: Option<i32>,
+    pub min_height: Option<i32>,
+    pub max_height: Option<i32>,
+    pub editable: Option<bool>,
+    ///states from STATES it must be in, or not be in with a ! in front e.g. "!enabled"
+    pub states: Vec<String>,
+    ///walking a menu which was opened by clicking its item
+    pub in_menu: Option<bool>,
+    ///hint it or not
+    pub include: Option<bool>,
+    ///walk its children or not
+    pub descend: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+struct Compiled {
+    rule: Rule,
+    name: Option<Regex>,
+    ///where it came from, to say why an element was excluded
+    origin: String,
+}
+
+impl Compiled {
+    fn matches(&self, element: &UiElement, menu: bool) -> bool {
+        let rule = &self.rule;
+        (rule.roles.is_empty() || rule.roles.contains(&element.control))
+            && self.name.as_ref().is_none_or(|n| n.is_match(&element.name))
+            && rule.min_width.is_none_or(|w| element.width >= w)
+            && rule.max_width.is_none_or(|w| element.width <= w)
+            && rule.min_height.is_none_or(|h| element.height >= h)
+            && rule.max_height.is_none_or(|h| element.height <= h)
+            && rule.editable.is_none_or(|e| element.editable == e)
+            && rule
+                .states
+                .iter()
+                .all(|state| match state.strip_prefix('!') {
+                    Some(state) => !element.states.iter().any(|s| s == state),
+                    None => element.states.iter().any(|s| s == state),
+                })
+            && rule.in_menu.is_none_or(|m| menu == m)
+    }
+
+    fn applies_to(&self, app: &str, pid: i32) -> bool {
+        let Some(wanted) = self.rule.app.as_deref() else {
+            return true;
+        };
+        let bare = |name: &str| name.to_lowercase().trim_end_matches(".exe").to_string();
+        wanted == pid.to_string() || bare(wanted) == bare(app)
+    }
+}
+
+///the rules from the config, then the defaults for this platform. the first rule which matches decides
+pub struct Rules {
+    rules: Vec<Compiled>,
+    debug: bool,
+    offscreen: bool,
+}
+
+impl Rules {
+    pub fn new(config: &[Rule], debug: bool) -> Self {
+        let config = config
+            .iter()
+            .enumerate()
+            .map(|(i, r)| (r.clone(), format!("config rule {}", i + 1)));
+        let defaults = default_rules()
+            .into_iter()
+            .enumerate()
+            .map(|(i, r)| (r, format!("default rule {}", i + 1)));
+        let rules = config
+            .chain(defaults)
+            .filter_map(|(rule, origin)| {
+                let name = match rule.name.as_deref().map(Regex::new).transpose() {
+                    Ok(name) => name,
+                    Err(e) => {
+                        println!("skipping {origin}, its name is not a valid regex: {e}");
+                        return None;
+                    }
+                };
+                let unknown = rule
+                    .states
+                    .iter()
+                    .find(|s| !STATES.contains(&s.trim_start_matches('!')));
+                if let Some(state) = unknown {
+                    println!("skipping {origin}, {state} is not one of {STATES:?}");
+                    return None;
+                }
+                Some(Compiled { rule, name, origin })
+            })
+            .collect();
+        Rules {
+            rules,
+            debug,
+            offscreen: false,
+        }
+    }
+
+    ///also list elements scrolled out of view
+    pub fn with_offscreen(mut self, offscreen: bool) -> Self {
+        self.offscreen = offscreen;
+        self
+    }
+
+    ///the rules for walking one application
+    pub fn for_app(&self, app: &str, pid: i32, menu: bool) -> AppRules {
+        AppRules {
+            rules: self
+                .rules
+                .iter()
+                .filter(|r| r.applies_to(app, pid))
+                .cloned()
+                .collect(),
+            menu,
+            debug: self.debug,
+            offscreen: self.offscreen,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AppRules {
+    rules: Vec<Compiled>,
+    menu: bool,
+    ///say why elements are excluded
+    pub debug: bool,
+    ///include elements scrolled out of view in scrollable containers, marked offscreen
+    pub offscreen: bool,
+}
+
+impl AppRules {
+    ///whether the element gets a hint, else the rule which excluded it
+    pub fn include(&self, element: &UiElement) -> Result<(), String> {
+        let rule = self
+            .rules
+            .iter()
+            .find(|r| r.rule.include.is_some() && r.matches(element, self.menu));
+        match rule {
+            Some(r) if r.rule.include == Some(false) => Err(r.origin.clone()),
+            _ => Ok(()),
+        }
+    }
+
+    ///whether any rule looks at states, which cost a call each on some backends
+    pub fn wants_states(&self) -> bool {
+        self.rules.iter().any(|r| !r.rule.states.is_empty())
+    }
+
+    ///whether to walk its children
+    pub fn descend(&self, element: &UiElement) -> bool {
+        self.rules
+            .iter()
+            .find(|r| r.rule.descend.is_some() && r.matches(element, self.menu))
+            .is_none_or(|r| r.rule.descend == Some(true))
+    }
+}
+
+fn roles(roles: &[&str]) -> Vec<String> {
+    roles.iter().map(|r| r.to_string()).collect()
+}
+
+///what was hinted before rules could be configured
+fn default_rules() -> Vec<Rule> {
+    let mut rules = vec![];
+    //grids, or we get every cell e.g. in excel
+    let grids: &[&str] = if cfg!(target_os = "windows") {
+        &["DataGrid"]
+    } else if cfg!(target_os = "linux") {
+        &["Table", "TreeTable", "DocumentSpreadsheet"]
+    } else {
+        &[]
+    };
+    if !grids.is_empty() {
+        rules.push(Rule {
+            roles: roles(grids),
+            descend: Some(false),
+            ..Default::default()
+        });
+    }
+    if cfg!(target_os = "macos") {
+        rules.extend([
+            //a menu item's children are its submenu, which is walked once it opens
+            Rule {
+                roles: roles(&["AXMenuItem"]),
+                in_menu: Some(true),
+                descend: Some(false),
+                ..Default::default()
+            },
+            //menus are always in the tree, even when closed
+            Rule {
+                roles: roles(&["AXMenuItem"]),
+                in_menu: Some(false),
+                include: Some(false),
+                ..Default::default()
+            },
+            //they highlight the entire window, or hold other items e.g. vscode's file list
+            Rule {
+                roles: roles(&[
+                    "AXApplication",
+                    "AXWindow",
+                    "AXWebArea",
+                    "AXOutline",
+                    "AXGroup",
+                    "AXRow",
+                ]),
+                include: Some(false),
+                ..Default::default()
+            },
+        ]);
+    }
+    //text boxes often have no name, they are found by their hint
+    rules.push(Rule {
+        editable: Some(true),
+        include: Some(true),
+        ..Default::default()
+    });
+    rules.push(Rule {
+        name: Some("^$".into()),
+        include: Some(false),
+        ..Default::default()
+    });
+    let hinted: &[&str] = if cfg!(target_os = "windows") {
+        &[
+            "Button",
+            "ListItem",
+            "TreeItem",
+            "Hyperlink",
+            "ComboBox",
+            "RadioButton",
+            "CheckBox",
+            "Edit",
+            "TabItem",
+            "MenuItem",
+        ]
+    } else if cfg!(target_os = "linux") {
+        &[
+            "PushButton",
+            "ToggleButton",
+            "CheckBox",
+            "RadioButton",
+            "ComboBox",
+            "Link",
+            "ListItem",
+            "TreeItem",
+            "PageTab",
+            "Menu", //menu bar items and submenus
+            "MenuItem",
+            "CheckMenuItem",
+            "RadioMenuItem",
+        ]
+    } else {
+        //everything else on mac
+        return rules;
+    };
+    rules.push(Rule {
+        roles: roles(hinted),
+        include: Some(true),
+        ..Default::default()
+    });
+    rules.push(Rule {
+        include: Some(false),
+        ..Default::default()
+    });
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(name: &str, control: &str) -> UiElement {
+        UiElement {
+            name: name.into(),
+            control: control.into(),
+            width: 40,
+            height: 20,
+            ..Default::default()
+        }
+    }
+
+    fn rule(roles: &[&str], include: bool) -> Rule {
+        Rule {
+            roles: super::roles(roles),
+            include: Some(include),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn config_rules_come_before_the_defaults() {
+        let rules = Rules::new(&[rule(&["Label"], true)], false).for_app("app", 1, false);
+        assert_eq!(rules.include(&element("Name", "Label")), Ok(()));
+        //no name, which a default rule excludes
+        let excluded = rules.include(&element("", "Image"));
+        assert!(excluded.is_err_and(|origin| origin.starts_with("default rule")));
+    }
+
+    #[test]
+    fn text_boxes_are_included_without_a_name() {
+        let rules = Rules::new(&[], false).for_app("app", 1, false);
+        let mut text = element("", "Text");
+        text.editable = true;
+        assert_eq!(rules.include(&text), Ok(()));
+    }
+
+    #[test]
+    fn the_excluding_rule_is_named() {
+        let rules = Rules::new(&[rule(&[], true), rule(&["Label"], false)], false);
+        let rules = rules.for_app("app", 1, false);
+        //the first rule matches everything, so the second never decides
+        assert_eq!(rules.include(&element("Name", "Label")), Ok(()));
+        let rules = Rules::new(&[rule(&["Label"], false)], false).for_app("app", 1, false);
+        assert_eq!(
+            rules.include(&element("Name", "Label")),
+            Err("config rule 1".to_string())
+        );
+    }
+
+    #[test]
+    fn all_conditions_have_to_match() {
+        let small = Rule {
+            name: Some("^Close".into()),
+            max_width: Some(30),
+            include: Some(false),
+            ..Default::default()
+        };
+        let rules = Rules::new(&[small, rule(&[], true)], false).for_app("app", 1, false);
+        let mut close = element("Close tab", "PushButton");
+        assert_eq!(rules.include(&close), Ok(()));
+        close.width = 20;
+        assert!(rules.include(&close).is_err());
+        close.name = "Open".into();
+        assert_eq!(rules.include(&close), Ok(()));
+    }
+
+    #[test]
+    fn rules_with_a_bad_regex_are_skipped() {
+        let bad = Rule {
+            name: Some("(".into()),
+            include: Some(false),
+            ..Default::default()
+        };
+        let rules = Rules::new(&[bad, rule(&[], true)], false).for_app("app", 1, false);
+        assert_eq!(rules.include(&element("(", "Label")), Ok(()));
+    }
+
+    #[test]
+    fn rules_apply_to_their_application() {
+        let firefox = Rule {
+            app: Some("Firefox.exe".into()),
+            ..rule(&["Label"], true)
+        };
+        let by_pid = Rule {
+            app: Some("42".into()),
+            ..rule(&["Image"], true)
+        };
+        let rules = Rules::new(&[firefox, by_pid, rule(&[], false)], false);
+        let label = element("Name", "Label");
+        let image = element("Name", "Image");
+        assert_eq!(rules.for_app("firefox", 7, false).include(&label), Ok(()));
+        assert!(rules
+            .for_app("thunderbird", 7, false)
+            .include(&label)
+            .is_err());
+        assert_eq!(rules.for_app("anything", 42, false).include(&image), Ok(()));
+        assert!(rules
+            .for_app("anything", 43, false)
+            .include(&image)
+            .is_err());
+    }
+
+    #[test]
+    fn descend_follows_the_first_matching_rule() {
+        let table = Rule {
+            roles: super::roles(&["Table"]),
+            descend: Some(false),
+            ..Default::default()
+        };
+        let rules = Rules::new(&[table], false).for_app("app", 1, false);
+        assert!(!rules.descend(&element("", "Table")));
+        assert!(rules.descend(&element("", "Panel")));
+    }
+
+    #[test]
+    fn menu_rules_only_apply_in_menus() {
+        let in_menu = Rule {
+            in_menu: Some(true),
+            ..rule(&["Separator"], false)
+        };
+        let rules = Rules::new(&[in_menu, rule(&[], true)], false);
+        let separator = element("Line", "Separator");
+        assert!(rules.for_app("app", 1, true).include(&separator).is_err());
+        assert_eq!(rules.for_app("app", 1, false).include(&separator), Ok(()));
+    }
+
+    #[test]
+    fn states_match_when_present_or_absent_with_a_bang() {
+        let disabled = Rule {
+            states: vec!["!enabled".into()],
+            ..rule(&[], false)
+        };
+        let checked = Rule {
+            states: vec!["checked".into()],
+            ..rule(&[], true)
+        };
+        let rules = Rules::new(&[disabled, checked, rule(&[], false)], false);
+        let rules = rules.for_app("app", 1, false);
+        assert!(rules.wants_states());
+        let mut check = element("Bold", "CheckBox");
+        assert!(rules.include(&check).is_err());
+        check.states = vec!["enabled".into()];
+        assert!(rules.include(&check).is_err());
+        check.states.push("checked".into());
+        assert_eq!(rules.include(&check), Ok(()));
+    }
+
+    #[test]
+    fn rules_with_an_unknown_state_are_skipped() {
+        let unknown = Rule {
+            states: vec!["pressed".into()],
+            ..rule(&[], false)
+        };
+        let rules = Rules::new(&[unknown, rule(&[], true)], false).for_app("app", 1, false);
+        assert!(!rules.wants_states());
+        assert_eq!(rules.include(&element("Name", "Label")), Ok(()));
+    }
+}
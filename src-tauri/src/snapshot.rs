@@ -0,0 +1,96 @@
+use std::{error::Error, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::traits::{ElementTree, Parent};
+
+///bump when the format changes, so old snapshots are rejected instead of misread
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+///everything a scan returned, so it can be replayed on another machine
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub os: String,
+    pub topmost: Option<Parent>,
+    pub tree: ElementTree,
+}
+
+impl Snapshot {
+    pub fn new(topmost: Option<Parent>, tree: ElementTree) -> Self {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            os: std::env::consts::OS.to_string(),
+            topmost,
+            tree,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text)?;
+        println!("saved {} elements to {path:?}", self.tree.nodes.len());
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let snapshot: Snapshot = serde_json::from_str(&text)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "snapshot version {} is not supported, expected {SNAPSHOT_VERSION}",
+                snapshot.version
+            )
+            .into());
+        }
+        println!(
+            "loaded {} elements from {} snapshot {path:?}",
+            snapshot.tree.nodes.len(),
+            snapshot.os
+        );
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::UiElement;
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("shortkey-test-{}-{name}.json", std::process::id()))
+    }
+
+    #[test]
+    fn saved_snapshots_load_again() {
+        let path = temp_file("round-trip");
+        let topmost = Parent {
+            name: "editor".into(),
+            pid: 7,
+            ..Default::default()
+        };
+        let tree = ElementTree::from_flat(vec![UiElement {
+            name: "Save".into(),
+            control: "Button".into(),
+            ..Default::default()
+        }]);
+        Snapshot::new(Some(topmost), tree).save(&path).unwrap();
+        let loaded = Snapshot::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.os, std::env::consts::OS);
+        assert_eq!(loaded.topmost.map(|t| t.pid), Some(7));
+        assert_eq!(loaded.tree.nodes[0].element.name, "Save");
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let path = temp_file("old-version");
+        let mut snapshot = Snapshot::new(None, ElementTree::default());
+        snapshot.version = SNAPSHOT_VERSION - 1;
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(loaded.is_err());
+    }
+}
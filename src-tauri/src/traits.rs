@@ -1,23 +1,322 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    sync::{mpsc::Sender, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use active_win_pos_rs::ActiveWindow;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    config::{ScanMode, WalkBudget},
+    monitor::Rect,
+};
+
 pub trait AccessibilityCalls {
-    ///check if has permissions
-    fn has_permissions(&self) -> bool;
-    ///get the elements which we can click on
-    fn get_elements(&mut self) -> Vec<UiElement>;
+    ///check everything we need from the os e.g. permissions, enabled services
+    fn check_prerequisites(&mut self) -> Vec<Prerequisite>;
+    ///try to turn on a prerequisite which is fixable
+    fn enable_prerequisite(&mut self, id: &str) -> Result<(), AccessError> {
+        Err(AccessError::Backend(format!(
+            "{id} cannot be enabled from here"
+        )))
+    }
+    ///get the elements which we can click on, along with the containers they are in.
+    ///they are also sent in batches while walking
+    fn get_elements(&mut self, batches: &mut Batches) -> Result<ElementTree, AccessError>;
+    ///what the backend's elements and windows are measured in
+    fn coord_space(&self) -> CoordSpace {
+        CoordSpace::Physical
+    }
     ///do the click event
-    fn invoke(&self, element: &UiElement, action: Action);
+    fn invoke(&self, element: &UiElement, action: Action) -> Result<(), AccessError>;
     ///we must call this before displaying the window
     fn save_topmost(&mut self);
+    ///the window saved by save_topmost
+    fn get_topmost(&self) -> Option<Parent>;
+    ///switch between the topmost window and all windows, for the next scan
+    fn set_scan_mode(&mut self, _mode: ScanMode) {}
+    ///the popup menu which opened after invoking the element, the innermost if there are submenus.
+    ///it replaces the scanned tree, so its items can be invoked
+    fn get_open_menu(&mut self, _from: &UiElement) -> Result<Option<ElementTree>, AccessError> {
+        Ok(None)
+    }
+    ///keep adding the pids of applications whose elements change, so their cached elements are dropped.
+    ///without it cached elements only expire
+    fn watch_changes(&mut self, _changes: Changes) {}
+    ///walk again after watch_changes saw changes, backends which know what changed only walk that
+    fn refresh(&mut self) -> Result<ElementTree, AccessError> {
+        self.get_elements(&mut Batches::none())
+    }
+    ///the windows on screen frontmost first, without ours, to skip elements another window covers.
+    ///their window_id and title are what elements are tagged with, or empty when they differ.
+    ///empty when the backend cannot tell
+    fn window_stack(&mut self) -> Vec<Parent> {
+        vec![]
+    }
+    ///scroll an element which is out of view into view, returns it where it is now
+    fn scroll_into_view(&mut self, element: &UiElement) -> Result<UiElement, AccessError> {
+        Err(AccessError::NoAction(element.name.clone()))
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+///pids of applications which changed since the cache was last checked
+pub type Changes = Arc<Mutex<HashSet<i32>>>;
+
+///the desktop's own surfaces which are not application windows e.g. taskbar, panels, tray icons
+pub trait ShellSurfaces {
+    ///walk them into the tree after the windows, tagged with the surface as their window
+    fn add_shell_surfaces(&mut self, batches: &mut Batches) -> Result<(), AccessError>;
+}
+
+///synthesized input, for when an element cannot be invoked through accessibility
+pub trait InputCalls {
+    ///move the pointer to absolute screen coordinates
+    fn move_to(&self, x: i32, y: i32) -> Result<(), AccessError>;
+    ///press or release a mouse button at the current position
+    fn button(&self, button: MouseButton, down: bool) -> Result<(), AccessError>;
+    ///press or release a modifier key
+    fn key(&self, key: Modifier, down: bool) -> Result<(), AccessError>;
+
+    fn click(&self, button: MouseButton) -> Result<(), AccessError> {
+        self.button(button, true)?;
+        self.button(button, false)
+    }
+
+    ///move to the centre of the element and do the action there
+    fn click_element(&self, element: &UiElement, action: &Action) -> Result<(), AccessError> {
+        //the pointer cannot do these, so dont move it
+        if let Action::Focus | Action::Type(_) = action {
+            return Err(AccessError::NoAction(element.name.clone()));
+        }
+        let x = element.x + element.width / 2;
+        let y = element.y + element.height / 2;
+        self.move_to(x, y)?;
+        match action {
+            Action::LeftClick => self.click(MouseButton::Left),
+            Action::RightClick => self.click(MouseButton::Right),
+            Action::MiddleClick => self.click(MouseButton::Middle),
+            Action::DoubleClick => {
+                self.click(MouseButton::Left)?;
+                self.click(MouseButton::Left)
+            }
+            Action::Hover | Action::Focus | Action::Type(_) => Ok(()),
+            Action::ShiftClick | Action::CtrlClick => {
+                let key = if let Action::ShiftClick = action {
+                    Modifier::Shift
+                } else {
+                    Modifier::Control
+                };
+                self.key(key, true)?;
+                let res = self.click(MouseButton::Left);
+                //always let go of the key
+                self.key(key, false)?;
+                res
+            }
+        }
+    }
+}
+
+///one thing we need from the os, and whether we have it
+#[derive(Debug, Clone, Serialize)]
+pub struct Prerequisite {
+    ///passed back to enable_prerequisite
+    pub id: String,
+    pub description: String,
+    pub ok: bool,
+    ///we can turn it on ourselves with enable_prerequisite
+    pub fixable: bool,
+    ///what the user can do about it
+    pub hint: String,
+}
+
+impl Prerequisite {
+    pub fn new(id: &str, description: &str, ok: bool) -> Self {
+        Prerequisite {
+            id: id.to_string(),
+            description: description.to_string(),
+            ok,
+            fixable: false,
+            hint: String::new(),
+        }
+    }
+
+    pub fn hint(mut self, hint: &str) -> Self {
+        self.hint = hint.to_string();
+        self
+    }
+
+    pub fn fixable(mut self) -> Self {
+        self.fixable = true;
+        self
+    }
+}
+
+///why a scan or invoke did not work, in terms the user can act on
+#[derive(Debug, Clone)]
+pub enum AccessError {
+    ///the element went away or changed since the scan
+    Stale(String),
+    ///accessibility has not been allowed or enabled for us
+    PermissionDenied,
+    ///none of the invoke strategies can do the action on the element
+    NoAction(String),
+    ///synthesized input could not be sent
+    InjectionFailed(String),
+    ///the application did not answer in time
+    Timeout,
+    ///any other error from the platform api
+    Backend(String),
+}
+
+impl AccessError {
+    ///name of the variant, for the frontend
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AccessError::Stale(_) => "Stale",
+            AccessError::PermissionDenied => "PermissionDenied",
+            AccessError::NoAction(_) => "NoAction",
+            AccessError::InjectionFailed(_) => "InjectionFailed",
+            AccessError::Timeout => "Timeout",
+            AccessError::Backend(_) => "Backend",
+        }
+    }
+}
+
+impl Display for AccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessError::Stale(name) => write!(f, "'{name}' is no longer available"),
+            AccessError::PermissionDenied => write!(f, "accessibility access is not allowed"),
+            AccessError::NoAction(name) => write!(f, "'{name}' cannot be invoked that way"),
+            AccessError::InjectionFailed(e) => write!(f, "could not send input: {e}"),
+            AccessError::Timeout => write!(f, "the application did not respond in time"),
+            AccessError::Backend(e) => write!(f, "accessibility error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Action {
     LeftClick,
     RightClick,
+    MiddleClick,
+    DoubleClick,
+    ///only move the mouse there e.g. for tooltips
+    Hover,
+    ///give keyboard focus without clicking
+    Focus,
+    ShiftClick,
+    CtrlClick,
+    ///replace the text of an editable element
+    Type(String),
 }
-#[derive(Clone, Debug)]
+
+///ways of invoking an element
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Strategy {
+    ///the element's own accessibility action, works when covered and leaves the pointer alone
+    Native,
+    ///synthesized input at the centre of the element
+    Pointer,
+}
+
+///the element where it stopped after scrolling it into view, stale if it could not be found
+pub fn scrolled_to(
+    element: &UiElement,
+    bounds: impl FnMut() -> Option<Rect>,
+) -> Result<UiElement, AccessError> {
+    let rect = settle(bounds).ok_or_else(|| AccessError::Stale(element.name.clone()))?;
+    Ok(UiElement {
+        x: rect.x,
+        y: rect.y,
+        width: rect.width,
+        height: rect.height,
+        offscreen: false,
+        ..element.clone()
+    })
+}
+
+///wait for an element which was scrolled to stop moving, scrolling is often animated.
+///returns where it stopped, none if it could not be found
+fn settle(mut bounds: impl FnMut() -> Option<Rect>) -> Option<Rect> {
+    let start = Instant::now();
+    let mut last = bounds()?;
+    while start.elapsed() < Duration::from_millis(1000) {
+        std::thread::sleep(Duration::from_millis(50));
+        let now = bounds()?;
+        if now == last {
+            break;
+        }
+        last = now;
+    }
+    Some(last)
+}
+
+///names keep only letters, digits and spaces, which is what is typed to match them
+pub fn clean(name: &str) -> String {
+    name.replace(|a: char| !(a.is_alphanumeric() || a.is_whitespace()), "")
+}
+
+///try each strategy in order until one works.
+///NoAction moves on quietly, other errors are kept to report if nothing works
+pub fn invoke_in_order(
+    order: &[Strategy],
+    element: &UiElement,
+    mut invoke: impl FnMut(Strategy) -> Result<(), AccessError>,
+) -> Result<(), AccessError> {
+    let mut error = AccessError::NoAction(element.name.clone());
+    for strategy in order {
+        match invoke(*strategy) {
+            Ok(()) => return Ok(()),
+            Err(AccessError::NoAction(_)) => {}
+            Err(e) => {
+                println!("could not invoke with {strategy:?}: {e}");
+                error = e;
+            }
+        }
+    }
+    Err(error)
+}
+
+impl TryFrom<&str> for Action {
+    type Error = String;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "LeftClick" => Ok(Action::LeftClick),
+            "RightClick" => Ok(Action::RightClick),
+            "MiddleClick" => Ok(Action::MiddleClick),
+            "DoubleClick" => Ok(Action::DoubleClick),
+            "Hover" => Ok(Action::Hover),
+            "Focus" => Ok(Action::Focus),
+            "ShiftClick" => Ok(Action::ShiftClick),
+            "CtrlClick" => Ok(Action::CtrlClick),
+            "Type" => Err("Type needs the text to type".into()),
+            _ => Err(format!("unknown action '{s}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Modifier {
+    Shift,
+    Control,
+}
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct UiElement {
+    ///issued by the backend to find the element again e.g. runtime id, object path or tree path
+    pub id: String,
     pub name: String,
     pub x: i32,
     pub y: i32,
@@ -28,4 +327,419 @@ pub struct UiElement {
     pub parent: String,
     pub x_offset: i32,
     pub y_offset: i32,
+    ///name of the closest named container e.g. the dialog, to tell apart elements with the same name
+    pub group: String,
+    ///takes text with Action::Type
+    pub editable: bool,
+    ///invoking it opens a menu, which we hint next
+    pub opens_menu: bool,
+    ///the top level window the element is in, issued by the backend
+    pub window_id: String,
+    ///title of that window
+    pub window: String,
+    ///what x, y, width and height are measured in
+    pub space: CoordSpace,
+    ///what its containers show of the screen e.g. a scrolled list, none when they have no size
+    pub clip: Option<Rect>,
+    ///scrolled out of view, so it is listed but not hinted on screen until it is scrolled to
+    pub offscreen: bool,
+    ///those of rules::STATES it is in, the backends which ask for each only fill them in for rules
+    pub states: Vec<String>,
+}
+
+impl UiElement {
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+///what the accessibility api measures elements in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum CoordSpace {
+    ///device pixels, e.g. x11, and windows when dpi aware
+    #[default]
+    Physical,
+    ///pixels divided by the monitor's scale factor e.g. points on mac
+    Logical,
+}
+
+///elements in the order they were walked, with links to their parent and children
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ElementTree {
+    pub nodes: Vec<Node>,
+    ///the limit which cut the walk short, so some elements may be missing
+    #[serde(default)]
+    pub truncated: Option<Truncation>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Node {
+    ///index into nodes, the same for the same tree walked again
+    pub id: usize,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub depth: usize,
+    ///false for containers, which we only keep for structure
+    pub include: bool,
+    pub element: UiElement,
+}
+
+///how far an element found by name can have moved, in pixels across and down
+const RESOLVE_DISTANCE: i32 = 40;
+
+impl ElementTree {
+    ///every element is its own root
+    pub fn from_flat(elements: Vec<UiElement>) -> Self {
+        let mut tree = ElementTree::default();
+        for el in elements {
+            tree.add(None, el, true);
+        }
+        tree
+    }
+
+    ///returns the id of the new node
+    pub fn add(&mut self, parent: Option<usize>, element: UiElement, include: bool) -> usize {
+        let id = self.nodes.len();
+        let depth = parent.map(|p| self.nodes[p].depth + 1).unwrap_or_default();
+        if let Some(p) = parent {
+            self.nodes[p].children.push(id);
+        }
+        self.nodes.push(Node {
+            id,
+            parent,
+            children: vec![],
+            depth,
+            include,
+            element,
+        });
+        id
+    }
+
+    ///closest first
+    pub fn ancestors(&self, id: usize) -> impl Iterator<Item = &Node> {
+        std::iter::successors(self.nodes[id].parent, |&p| self.nodes[p].parent)
+            .map(|p| &self.nodes[p])
+    }
+
+    ///the included elements in walk order, which is what hints are made from
+    pub fn flatten(&self) -> Vec<UiElement> {
+        self.flatten_from(0)
+    }
+
+    ///the included elements from the node with this id on
+    pub fn flatten_from(&self, first: usize) -> Vec<UiElement> {
+        self.nodes[first.min(self.nodes.len())..]
+            .iter()
+            .filter(|n| n.include)
+            .map(|n| {
+                let mut el = n.element.clone();
+                el.group = self.group(n.id).to_string();
+                el.clip = self.clip(n.id);
+                el
+            })
+            .collect()
+    }
+
+    ///name of the closest named container, empty if none is
+    pub fn group(&self, id: usize) -> &str {
+        self.ancestors(id)
+            .map(|a| a.element.name.as_str())
+            .find(|name| !name.is_empty())
+            .unwrap_or_default()
+    }
+
+    ///what the node's containers show, the overlap of those which have a size
+    pub fn clip(&self, id: usize) -> Option<Rect> {
+        self.ancestors(id)
+            .filter(|a| a.element.width > 0 && a.element.height > 0)
+            .map(|a| a.element.rect())
+            .reduce(|a, b| a.intersection(&b))
+    }
+
+    ///whether the node or one of its containers is one of these controls
+    pub fn within(&self, id: usize, controls: &[&str]) -> bool {
+        std::iter::once(&self.nodes[id])
+            .chain(self.ancestors(id))
+            .any(|n| controls.contains(&n.element.control.as_str()))
+    }
+
+    ///the node with the element's id, else the closest one with the same name and control
+    ///in the same window and group. none if it moved further than that, as it is likely another one
+    pub fn resolve(&self, element: &UiElement) -> Option<&Node> {
+        if !element.id.is_empty() {
+            if let Some(node) = self.nodes.iter().find(|n| n.element.id == element.id) {
+                return Some(node);
+            }
+        }
+        let distance = |n: &Node| (n.element.x - element.x).abs() + (n.element.y - element.y).abs();
+        self.nodes
+            .iter()
+            .filter(|n| {
+                let e = &n.element;
+                n.include
+                    && e.name == element.name
+                    && e.control == element.control
+                    && e.pid == element.pid
+                    && e.window_id == element.window_id
+                    && self.group(n.id) == element.group
+            })
+            .filter(|n| distance(n) <= RESOLVE_DISTANCE)
+            .min_by_key(|n| distance(n))
+    }
+}
+
+///included elements sent while walking, so hints show before a long walk finishes
+#[derive(Clone)]
+pub struct Batches {
+    sender: Option<Sender<Vec<UiElement>>>,
+    ///nodes before this were sent
+    sent: usize,
+    last: Instant,
+}
+
+impl Batches {
+    const INTERVAL: Duration = Duration::from_millis(150);
+
+    pub fn new(sender: Sender<Vec<UiElement>>) -> Self {
+        Batches {
+            sender: Some(sender),
+            sent: 0,
+            last: Instant::now(),
+        }
+    }
+
+    ///for walks nobody is waiting on
+    pub fn none() -> Self {
+        Batches {
+            sender: None,
+            sent: 0,
+            last: Instant::now(),
+        }
+    }
+
+    ///call after adding nodes, sends them when the last batch was long enough ago
+    pub fn walked(&mut self, tree: &ElementTree) {
+        if self.sender.is_some() && self.last.elapsed() >= Self::INTERVAL {
+            self.flush(tree);
+        }
+    }
+
+    ///send the included elements added since the last batch
+    pub fn flush(&mut self, tree: &ElementTree) {
+        let batch = tree.flatten_from(self.sent);
+        self.sent = tree.nodes.len();
+        self.last = Instant::now();
+        if let (Some(sender), false) = (self.sender.as_ref(), batch.is_empty()) {
+            //nobody listening is fine, the tree is returned anyway
+            let _ = sender.send(batch);
+        }
+    }
+}
+
+///which walk budget ran out
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Truncation {
+    ///a branch went deeper than allowed
+    Depth,
+    Elements,
+    Time,
+}
+
+///keeps a scan within its budget, walks stop when it is spent and keep what they found
+#[derive(Clone, Debug)]
+pub struct Budget {
+    limits: WalkBudget,
+    start: Instant,
+    pub truncated: Option<Truncation>,
+}
+
+impl Budget {
+    pub fn new(limits: &WalkBudget) -> Self {
+        Budget {
+            limits: limits.clone(),
+            start: Instant::now(),
+            truncated: None,
+        }
+    }
+
+    ///true once out of elements or time
+    pub fn spent(&mut self, tree: &ElementTree) -> bool {
+        self.spent_after(tree.nodes.len())
+    }
+
+    ///the same for searches which keep no tree, given how many elements they looked at
+    pub fn spent_after(&mut self, elements: usize) -> bool {
+        let reason = if elements >= self.limits.max_elements {
+            Truncation::Elements
+        } else if self.start.elapsed() >= Duration::from_millis(self.limits.max_millis) {
+            Truncation::Time
+        } else {
+            return false;
+        };
+        if self.truncated != Some(reason) {
+            println!("walk stopped early, {reason:?} budget spent");
+        }
+        self.truncated = Some(reason);
+        true
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.limits.max_depth
+    }
+
+    ///whether to walk the children of the node just added.
+    ///has_children is only asked at the depth limit, as a leaf there cut nothing off
+    pub fn descend(&mut self, node: &Node, has_children: impl FnOnce() -> bool) -> bool {
+        if node.depth >= self.limits.max_depth {
+            if has_children() {
+                self.truncated.get_or_insert(Truncation::Depth);
+            }
+            return false;
+        }
+        true
+    }
+}
+
+///the window we get elements from
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Parent {
+    pub name: String,
+    pub pid: i32,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub window_id: String,
+}
+
+impl Parent {
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+impl From<ActiveWindow> for Parent {
+    fn from(win: ActiveWindow) -> Self {
+        Parent {
+            name: win.app_name,
+            title: win.title,
+            window_id: win.window_id,
+            pid: win.process_id as i32,
+            x: win.position.x as i32,
+            y: win.position.y as i32,
+            width: win.position.width as i32,
+            height: win.position.height as i32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(name: &str, x: i32, y: i32) -> UiElement {
+        UiElement {
+            name: name.into(),
+            control: "Button".into(),
+            x,
+            y,
+            width: 20,
+            height: 20,
+            ..Default::default()
+        }
+    }
+
+    ///a dialog with an ok button, and another dialog with one too
+    fn dialogs() -> ElementTree {
+        let mut tree = ElementTree::default();
+        let open = tree.add(None, element("Open", 0, 0), false);
+        tree.add(Some(open), element("OK", 100, 100), true);
+        let save = tree.add(None, element("Save", 0, 0), false);
+        tree.add(Some(save), element("OK", 110, 100), true);
+        tree
+    }
+
+    fn budget(max_depth: usize) -> Budget {
+        Budget::new(&WalkBudget {
+            max_depth,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn leaves_at_the_depth_limit_cut_nothing_off() {
+        let mut tree = ElementTree::default();
+        let root = tree.add(None, element("Window", 0, 0), false);
+        let leaf = tree.add(Some(root), element("OK", 0, 0), true);
+        let mut budget = budget(1);
+        assert!(budget.descend(&tree.nodes[root], || true));
+        assert!(!budget.descend(&tree.nodes[leaf], || false));
+        assert_eq!(budget.truncated, None);
+    }
+
+    #[test]
+    fn containers_at_the_depth_limit_truncate() {
+        let mut tree = ElementTree::default();
+        let root = tree.add(None, element("Window", 0, 0), false);
+        let list = tree.add(Some(root), element("List", 0, 0), false);
+        let mut budget = budget(1);
+        assert!(!budget.descend(&tree.nodes[list], || true));
+        assert_eq!(budget.truncated, Some(Truncation::Depth));
+    }
+
+    #[test]
+    fn children_are_only_asked_for_at_the_limit() {
+        let mut tree = ElementTree::default();
+        let root = tree.add(None, element("Window", 0, 0), false);
+        let mut budget = budget(1);
+        assert!(budget.descend(&tree.nodes[root], || unreachable!()));
+    }
+
+    #[test]
+    fn resolve_by_id() {
+        let mut tree = dialogs();
+        tree.nodes[3].element.id = "ok".into();
+        let wanted = UiElement {
+            id: "ok".into(),
+            ..Default::default()
+        };
+        assert_eq!(tree.resolve(&wanted).map(|n| n.id), Some(3));
+    }
+
+    #[test]
+    fn resolve_by_name_in_the_same_group() {
+        let tree = dialogs();
+        let mut wanted = element("OK", 112, 100);
+        wanted.group = "Open".into();
+        assert_eq!(tree.resolve(&wanted).map(|n| n.id), Some(1));
+        wanted.group = "Save".into();
+        assert_eq!(tree.resolve(&wanted).map(|n| n.id), Some(3));
+    }
+
+    #[test]
+    fn resolve_gives_up_on_elements_elsewhere() {
+        let tree = dialogs();
+        let mut wanted = element("OK", 400, 100);
+        wanted.group = "Open".into();
+        assert!(tree.resolve(&wanted).is_none());
+        //the id is gone, it does not fall back to one in another window
+        let mut wanted = element("OK", 100, 100);
+        wanted.id = "gone".into();
+        wanted.group = "Open".into();
+        wanted.window_id = "other".into();
+        assert!(tree.resolve(&wanted).is_none());
+    }
 }
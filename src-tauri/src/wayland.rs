@@ -0,0 +1,223 @@
+use std::{error::Error, time::Instant};
+
+use wayland_client::{
+    delegate_noop,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_output::{self, WlOutput},
+        wl_pointer::ButtonState,
+        wl_registry::WlRegistry,
+        wl_seat::WlSeat,
+    },
+    Connection, Dispatch, QueueHandle, WEnum,
+};
+use wayland_protocols_wlr::virtual_pointer::v1::client::{
+    zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1,
+    zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1,
+};
+
+use crate::{
+    monitor::Rect,
+    traits::{AccessError, InputCalls, Modifier, MouseButton},
+};
+
+//linux input event codes
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+
+///pointer injection on wlroots based compositors through the virtual pointer protocol
+pub struct Wayland {
+    conn: Connection,
+    pointer: ZwlrVirtualPointerV1,
+    //absolute motion is relative to the bounding box of all outputs, in logical pixels
+    origin: (i32, i32),
+    extent: (u32, u32),
+    start: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    outputs: Vec<Output>,
+}
+
+///position is logical, the mode is in physical pixels
+#[derive(Default)]
+struct Output {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    scale: i32,
+    ///rotated a quarter turn, so the mode's width is its height
+    sideways: bool,
+}
+
+impl Output {
+    ///where it is in the compositor's layout, which is in logical pixels
+    fn logical(&self) -> Rect {
+        let scale = self.scale.max(1);
+        let (width, height) = match self.sideways {
+            false => (self.width, self.height),
+            true => (self.height, self.width),
+        };
+        Rect {
+            x: self.x,
+            y: self.y,
+            width: width / scale,
+            height: height / scale,
+        }
+    }
+}
+
+impl Wayland {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let conn = Connection::connect_to_env()?;
+        let (globals, mut queue) = registry_queue_init::<State>(&conn)?;
+        let qh = queue.handle();
+        let mut state = State::default();
+
+        let seat: WlSeat = globals.bind(&qh, 1..=1, ())?;
+        let manager: ZwlrVirtualPointerManagerV1 = globals.bind(&qh, 1..=2, ())?;
+        let pointer = manager.create_virtual_pointer(Some(&seat), &qh, ());
+
+        let outputs = globals.contents().with_list(|list| {
+            list.iter()
+                .filter(|g| g.interface == "wl_output")
+                .map(|g| (g.name, g.version))
+                .collect::<Vec<_>>()
+        });
+        for (index, (name, version)) in outputs.into_iter().enumerate() {
+            state.outputs.push(Output::default());
+            globals
+                .registry()
+                .bind::<WlOutput, _, _>(name, version.min(2), &qh, index);
+        }
+        //get geometry and modes of the outputs
+        queue.roundtrip(&mut state)?;
+
+        //outputs left of or above the first are at negative positions
+        let bounds = Rect::union(
+            &state
+                .outputs
+                .iter()
+                .map(|o| o.logical())
+                .collect::<Vec<_>>(),
+        );
+        let (origin, extent) = match bounds {
+            Some(b) if b.width > 0 && b.height > 0 => {
+                ((b.x, b.y), (b.width as u32, b.height as u32))
+            }
+            _ => return Err("no outputs found".into()),
+        };
+        println!("virtual pointer over {extent:?} from {origin:?}");
+
+        Ok(Wayland {
+            conn,
+            pointer,
+            origin,
+            extent,
+            start: Instant::now(),
+        })
+    }
+
+    fn time(&self) -> u32 {
+        self.start.elapsed().as_millis() as u32
+    }
+
+    fn flush(&self) -> Result<(), AccessError> {
+        self.pointer.frame();
+        self.conn
+            .flush()
+            .map_err(|e| AccessError::InjectionFailed(e.to_string()))
+    }
+}
+
+impl InputCalls for Wayland {
+    fn move_to(&self, x: i32, y: i32) -> Result<(), AccessError> {
+        let x = (x - self.origin.0).clamp(0, self.extent.0 as i32 - 1) as u32;
+        let y = (y - self.origin.1).clamp(0, self.extent.1 as i32 - 1) as u32;
+        self.pointer
+            .motion_absolute(self.time(), x, y, self.extent.0, self.extent.1);
+        self.flush()
+    }
+
+    fn button(&self, button: MouseButton, down: bool) -> Result<(), AccessError> {
+        let button = match button {
+            MouseButton::Left => BTN_LEFT,
+            MouseButton::Middle => BTN_MIDDLE,
+            MouseButton::Right => BTN_RIGHT,
+        };
+        let state = if down {
+            ButtonState::Pressed
+        } else {
+            ButtonState::Released
+        };
+        self.pointer.button(self.time(), button, state);
+        self.flush()
+    }
+
+    fn key(&self, key: Modifier, _down: bool) -> Result<(), AccessError> {
+        //would need the virtual keyboard protocol and a keymap
+        Err(AccessError::InjectionFailed(format!(
+            "holding {key:?} is not supported on wayland"
+        )))
+    }
+}
+
+impl Dispatch<WlOutput, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wl_output::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = &mut state.outputs[*index];
+        match event {
+            wl_output::Event::Geometry {
+                x, y, transform, ..
+            } => {
+                output.x = x;
+                output.y = y;
+                output.sideways = matches!(
+                    transform,
+                    WEnum::Value(
+                        wl_output::Transform::_90
+                            | wl_output::Transform::_270
+                            | wl_output::Transform::Flipped90
+                            | wl_output::Transform::Flipped270
+                    )
+                );
+            }
+            wl_output::Event::Scale { factor } => output.scale = factor,
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => {
+                output.width = width;
+                output.height = height;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as wayland_client::Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: ignore WlSeat);
+delegate_noop!(State: ZwlrVirtualPointerManagerV1);
+delegate_noop!(State: ZwlrVirtualPointerV1);
@@ -1,249 +1,547 @@
-use std::{fmt::Display, vec};
-
-use crate::traits::{AccessibilityCalls, Action, UiElement};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    config::{Config, ScanMode},
+    monitor::Rect,
+    rules::{AppRules, Rules},
+    traits::{
+        clean, invoke_in_order, scrolled_to, AccessError, AccessibilityCalls, Action, Batches,
+        Budget, Changes, CoordSpace, ElementTree, Parent, Prerequisite, ShellSurfaces, Strategy,
+        UiElement,
+    },
+};
+use ::windows::{
+    core::implement,
+    Win32::{
+        System::{Com::SAFEARRAY, Variant::VARIANT},
+        UI::Accessibility::{
+            IUIAutomation, IUIAutomationCacheRequest, IUIAutomationElement,
+            IUIAutomationPropertyChangedEventHandler,
+            IUIAutomationPropertyChangedEventHandler_Impl,
+            IUIAutomationStructureChangedEventHandler,
+            IUIAutomationStructureChangedEventHandler_Impl, StructureChangeType, TreeScope_Subtree,
+            UIA_BoundingRectanglePropertyId, UIA_IsOffscreenPropertyId, UIA_NamePropertyId,
+            UIA_PROPERTY_ID,
+        },
+    },
+};
 use active_win_pos_rs::get_active_window;
-use easier::prelude::ToCollectionIteratorExtension;
-use uiautomation::{controls::ControlType, Error, UIAutomation, UIElement};
+use sysinfo::{PidExt, ProcessExt, ProcessRefreshKind, RefreshKind, System, SystemExt};
+use uiautomation::{
+    controls::ControlType,
+    patterns::{
+        UIExpandCollapsePattern, UIInvokePattern, UIScrollItemPattern, UISelectionItemPattern,
+        UITogglePattern, UIValuePattern,
+    },
+    types::{ExpandCollapseState, ToggleState},
+    Error, UIAutomation, UIElement, UITreeWalker,
+};
+
+///the properties whose changes move or rename hints, like bounds-changed on linux
+const CHANGE_PROPERTIES: &[UIA_PROPERTY_ID] = &[
+    UIA_BoundingRectanglePropertyId,
+    UIA_NamePropertyId,
+    UIA_IsOffscreenPropertyId,
+];
 
 pub struct Windows {
     topmost: Option<Parent>,
-    debug: bool,
-    show_taskbar: bool,
+    rules: Rules,
+    config: Config,
+    tree: ElementTree,
+    //indexed by node id
     elements: Vec<UIElement>,
-}
-
-#[derive(Debug)]
-struct Parent {
-    pid: i32,
-    name: String,
+    //of the current scan, shared with the taskbar
+    budget: Budget,
+    //the application being hinted, whose changes are the only ones forwarded. none for all of them
+    watched: Arc<Mutex<Option<i32>>>,
+    //the handlers are registered with it, and removed when it is dropped
+    watcher: Option<UIAutomation>,
 }
 
 impl Windows {
-    pub fn new(debug: bool, show_taskbar: bool) -> Self {
+    pub fn new(debug: bool, config: Config) -> Self {
         Windows {
             topmost: None,
-            debug,
-            show_taskbar,
+            rules: Rules::new(&config.rules, debug).with_offscreen(config.list_offscreen),
+            budget: Budget::new(&config.budget),
+            config,
+            tree: ElementTree::default(),
             elements: Vec::new(),
+            watched: Arc::default(),
+            watcher: None,
         }
     }
+
+    fn watch_hinted(&self) {
+        *self.watched.lock().unwrap() = match self.config.scan_mode {
+            ScanMode::Topmost => self.topmost.as_ref().map(|t| t.pid),
+            ScanMode::AllWindows => None,
+        };
+    }
 }
 
 impl AccessibilityCalls for Windows {
-    fn get_elements(&mut self) -> Vec<UiElement> {
+    fn get_elements(
+        &mut self,
+        batches: &mut Batches,
+    ) -> std::result::Result<ElementTree, AccessError> {
         let start = std::time::Instant::now();
         println!("Starting to get elements");
+        self.tree = ElementTree::default();
         self.elements.clear();
-        let mut result = vec![];
+        self.budget = Budget::new(&self.config.budget);
         //get from upmost window
-        if let Some(topmost) = self.topmost.as_ref() {
-            let elements = get_elements_pid(topmost.pid, self.debug);
-            result.extend(elements.iter().map(|a| {
-                let mut el = UiElement::from(a);
-                el.parent = topmost
-                    .name
-                    .replace(|a: char| !(a.is_alphanumeric() || a.is_whitespace()), "")
-                    .clone();
-                el
-            }));
+        if let (ScanMode::Topmost, Some(topmost)) = (self.config.scan_mode, self.topmost.as_ref()) {
+            let first = self.tree.nodes.len();
+            if let Some(root) = get_root_pid(topmost.pid) {
+                add_elements_from_root(
+                    &root,
+                    &mut self.tree,
+                    &mut self.elements,
+                    batches,
+                    &mut self.budget,
+                    &self.rules.for_app(&topmost.name, topmost.pid, false),
+                )
+                .map_err(access_error(&topmost.name))?;
+            }
+            let parent = clean(&topmost.name);
+            for node in self.tree.nodes[first..].iter_mut() {
+                node.element.parent = parent.clone();
+                node.element.window_id = topmost.window_id.clone();
+                node.element.window = clean(&topmost.title);
+            }
             println!(
                 "got {} topmost elements in {}ms",
-                elements.len(),
+                self.tree.nodes.len() - first,
                 start.elapsed().as_millis()
             );
-            self.elements.extend(elements);
         }
 
-        //get from taskbar
-        if self.show_taskbar {
-            let elements = get_elements_taskbar();
-            result.extend(elements.iter().map(|a| {
-                let mut el = UiElement::from(a);
-                el.parent = "taskbar".into();
-                el
-            }));
-
+        //get from every window, in z order
+        if let ScanMode::AllWindows = self.config.scan_mode {
+            let names = process_names();
+            for window in visible_windows().map_err(access_error("desktop"))? {
+                let first = self.tree.nodes.len();
+                //it closed since we listed it
+                let Ok(info) = UiElement::try_from(&window) else {
+                    continue;
+                };
+                let app = names.get(&info.pid).map(String::as_str).unwrap_or_default();
+                add_elements_from_root(
+                    &window,
+                    &mut self.tree,
+                    &mut self.elements,
+                    batches,
+                    &mut self.budget,
+                    &self.rules.for_app(app, info.pid, false),
+                )
+                .map_err(access_error(&info.name))?;
+                let (parent, title) = (clean(app), clean(&info.name));
+                for node in self.tree.nodes[first..].iter_mut() {
+                    node.element.parent = parent.clone();
+                    node.element.window_id = info.id.clone();
+                    node.element.window = title.clone();
+                }
+            }
             println!(
-                "got {} taskbar elements in {}ms",
-                elements.len(),
+                "got {} window elements in {}ms",
+                self.tree.nodes.len(),
                 start.elapsed().as_millis()
             );
-            self.elements.extend(elements);
         }
 
-        let uivec = result
-            .into_iter()
-            .filter(|a| !a.name.is_empty()) //exclude empty names
-            .to_vec();
+        if self.config.shell_surfaces {
+            self.add_shell_surfaces(batches)?;
+        }
+        self.tree.truncated = self.budget.truncated;
 
         println!(
             "Got {} elements in {}ms",
-            uivec.len(),
+            self.tree.nodes.iter().filter(|n| n.include).count(),
             start.elapsed().as_millis()
         );
 
-        uivec
+        Ok(self.tree.clone())
     }
 
-    fn invoke(&self, element: &UiElement, action: Action) {
+    fn invoke(&self, element: &UiElement, action: Action) -> std::result::Result<(), AccessError> {
         //it will either be in start button or active window
         let start = std::time::Instant::now();
 
-        let ele = self
-            .elements
-            .iter()
-            .find(|&a| &UiElement::from(a) == element);
-        if let Some(ele) = ele {
-            invoke_element(ele, action);
-        } else {
+        let Some(ele) = self.tree.resolve(element).map(|n| &self.elements[n.id]) else {
             println!(
                 "no element found for {:?} out of {} elements",
                 element,
                 self.elements.len()
             );
-            for ele in self.elements.iter() {
-                println!("element: {:?}", UiElement::from(ele));
-            }
+            return Err(AccessError::Stale(element.name.clone()));
+        };
+        if !is_alive(ele) {
+            return Err(AccessError::Stale(element.name.clone()));
         }
+        println!("invoking {}", UI2(ele.clone()));
+        let res = invoke_in_order(&self.config.invoke_order, element, |strategy| {
+            let done = match strategy {
+                Strategy::Native => {
+                    invoke_native(ele, &action).map_err(access_error(&element.name))?
+                }
+                Strategy::Pointer => invoke_pointer(ele, &action)
+                    .map_err(|e| AccessError::InjectionFailed(e.to_string()))?,
+            };
+            if done {
+                Ok(())
+            } else {
+                Err(AccessError::NoAction(element.name.clone()))
+            }
+        });
         println!("invoked in {}ms", start.elapsed().as_millis());
+        res
     }
     fn save_topmost(&mut self) {
         let win = get_active_window();
         if let Ok(win) = win {
-            self.topmost = Some(Parent {
-                pid: win.process_id as i32,
-                name: win.app_name,
-            });
+            self.topmost = Some(Parent::from(win));
             println!("active window: {:?} ", self.topmost);
+            self.watch_hinted();
         } else {
             println!("no active window");
         }
     }
 
-    fn has_permissions(&self) -> bool {
-        true
+    fn get_topmost(&self) -> Option<Parent> {
+        self.topmost.clone()
+    }
+
+    fn set_scan_mode(&mut self, mode: ScanMode) {
+        self.config.scan_mode = mode;
+        self.watch_hinted();
+    }
+
+    fn watch_changes(&mut self, changes: Changes) {
+        let handler = ChangeHandler {
+            changes,
+            watched: self.watched.clone(),
+            own: std::process::id() as i32,
+        };
+        match watch(handler) {
+            Ok(automation) => self.watcher = Some(automation),
+            Err(e) => println!("not watching for changes, cached elements only expire: {e}"),
+        }
     }
+
+    fn scroll_into_view(
+        &mut self,
+        element: &UiElement,
+    ) -> std::result::Result<UiElement, AccessError> {
+        let Some(ele) = self.tree.resolve(element).map(|n| &self.elements[n.id]) else {
+            return Err(AccessError::Stale(element.name.clone()));
+        };
+        ele.get_pattern::<UIScrollItemPattern>()
+            .and_then(|item| item.scroll_into_view())
+            .map_err(access_error(&element.name))?;
+        scrolled_to(element, || {
+            let rect = ele.get_bounding_rectangle().ok()?;
+            Some(Rect {
+                x: rect.get_left(),
+                y: rect.get_top(),
+                width: rect.get_width(),
+                height: rect.get_height(),
+            })
+        })
+    }
+
+    fn window_stack(&mut self) -> Vec<Parent> {
+        let windows = match visible_windows() {
+            Ok(windows) => windows,
+            Err(e) => {
+                println!("no windows, not checking for covered elements: {e}");
+                return vec![];
+            }
+        };
+        windows
+            .iter()
+            .filter_map(|w| UiElement::try_from(w).ok())
+            .map(|info| {
+                //elements of the topmost window are tagged with its handle rather than its
+                //automation id, so they are matched by title
+                Parent {
+                    name: info.name.clone(),
+                    pid: info.pid,
+                    x: info.x,
+                    y: info.y,
+                    width: info.width,
+                    height: info.height,
+                    title: clean(&info.name),
+                    window_id: String::new(),
+                }
+            })
+            .collect()
+    }
+
+    fn get_open_menu(
+        &mut self,
+        from: &UiElement,
+    ) -> std::result::Result<Option<ElementTree>, AccessError> {
+        let Some(menu) = open_menu().map_err(access_error(&from.name))? else {
+            return Ok(None);
+        };
+        self.tree = ElementTree::default();
+        self.elements.clear();
+        add_elements_from_root(
+            &menu,
+            &mut self.tree,
+            &mut self.elements,
+            &mut Batches::none(),
+            &mut Budget::new(&self.config.budget),
+            &self.rules.for_app(&from.parent, from.pid, true),
+        )
+        .map_err(access_error(&from.name))?;
+        for node in self.tree.nodes.iter_mut() {
+            node.element.parent = from.parent.clone();
+            node.element.window = "menu".into();
+        }
+        Ok(Some(self.tree.clone()))
+    }
+
+    fn check_prerequisites(&mut self) -> Vec<Prerequisite> {
+        let automation = UIAutomation::new();
+        vec![Prerequisite::new(
+            "uiautomation",
+            "UI Automation is available",
+            automation.is_ok(),
+        )
+        .hint("UI Automation could not be created, try restarting shortkey")]
+    }
+}
+
+//HRESULTs from UIAutomationCoreApi.h
+const UIA_E_ELEMENTNOTAVAILABLE: u32 = 0x80040201;
+const UIA_E_TIMEOUT: u32 = 0x80131505;
+
+///maps errors about the named element, which is stale when it went away
+fn access_error(name: &str) -> impl Fn(Error) -> AccessError + '_ {
+    move |e| match e.code() as u32 {
+        UIA_E_ELEMENTNOTAVAILABLE => AccessError::Stale(name.to_string()),
+        UIA_E_TIMEOUT => AccessError::Timeout,
+        _ => AccessError::Backend(e.to_string()),
+    }
+}
+
+///elements which are gone return errors
+fn is_alive(ele: &UIElement) -> bool {
+    ele.get_runtime_id().is_ok() && ele.get_bounding_rectangle().is_ok()
 }
 
-fn invoke_element(ele: &UIElement, action: Action) {
-    println!("invoking {}", UI2(ele.clone()));
+///use the control patterns, returns false if the element has none for the action
+fn invoke_native(ele: &UIElement, action: &Action) -> Result<bool> {
+    match action {
+        Action::LeftClick => {
+            if let Ok(invoke) = ele.get_pattern::<UIInvokePattern>() {
+                invoke.invoke()?;
+            } else if let Ok(toggle) = ele.get_pattern::<UITogglePattern>() {
+                toggle.toggle()?;
+            } else if let Ok(expand) = ele.get_pattern::<UIExpandCollapsePattern>() {
+                match expand.get_state()? {
+                    ExpandCollapseState::Collapsed => expand.expand()?,
+                    _ => expand.collapse()?,
+                }
+            } else if let Ok(select) = ele.get_pattern::<UISelectionItemPattern>() {
+                select.select()?;
+            } else {
+                return Ok(false);
+            }
+            Ok(true)
+        }
+        Action::Focus => ele.set_focus().map(|_| true),
+        Action::Type(text) => {
+            let Ok(value) = ele.get_pattern::<UIValuePattern>() else {
+                return Ok(false);
+            };
+            if value.is_readonly()? {
+                return Ok(false);
+            }
+            ele.set_focus()?;
+            value.set_value(text)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn invoke_pointer(ele: &UIElement, action: &Action) -> Result<bool> {
     let mouse = uiautomation::inputs::Mouse::new().move_time(1);
     //let old = uiautomation::inputs::Mouse::get_cursor_pos().unwrap();
-    let rect = ele.get_bounding_rectangle().unwrap();
+    let rect = ele.get_bounding_rectangle()?;
     let pos = uiautomation::types::Point::new(
         rect.get_left() + rect.get_width() / 2,
         rect.get_top() + rect.get_height() / 2,
     );
     //let pos = ele.get_clickable_point().unwrap().unwrap();
 
-    mouse.move_to(pos).unwrap();
+    mouse.move_to(pos)?;
     match action {
-        Action::LeftClick => {
-            mouse.click(pos).unwrap();
-            //ele.click().unwrap();
+        Action::LeftClick => mouse.click(pos)?,
+        Action::RightClick => ele.right_click()?,
+        Action::DoubleClick => mouse.double_click(pos)?,
+        Action::MiddleClick => {
+            let _ = mouce::Mouse::new().click_button(&mouce::common::MouseButton::Middle);
         }
-        Action::RightClick => ele.right_click().unwrap(),
+        Action::Hover => {}
+        Action::Focus => return Ok(false),
+        Action::Type(text) => {
+            mouse.click(pos)?;
+            //braces start special keys
+            let text = text
+                .chars()
+                .map(|c| match c {
+                    '{' | '}' => format!("{{{c}}}"),
+                    c => c.to_string(),
+                })
+                .collect::<String>();
+            ele.send_keys(&text, 10)?;
+        }
+        Action::ShiftClick => mouse.holdkeys("{SHIFT}").click(pos)?,
+        Action::CtrlClick => mouse.holdkeys("{CONTROL}").click(pos)?,
     }
     // mouse.move_to(old).unwrap();
+    Ok(true)
 }
 
-fn get_elements_from_root(root_window: &UIElement, debug: bool) -> Vec<UIElement> {
-    let auto = UIAutomation::new().unwrap();
-
-    /*
-
-      let vec: Arc<Mutex<Vec<UIElement>>> = Arc::new(Mutex::new(Vec::new()));
-     let counter = Arc::new(AtomicU32::new(0));
-    let walker = auto.get_control_view_walker().unwrap();
-     let start = std::time::Instant::now();
-      println!(
-         "find_all {} in {}ms",
-         els.unwrap().len(),
-         start.elapsed().as_millis()
-     );
-
-     walk(
-         &walker,
-         &root_window,
-         vec.clone(),
-         counter.clone(),
-         pid,
-         0,
-         debug,
-     )
-     .unwrap();
-     println!(
-         "pid {pid} elements: {} out of {}",
-         vec.lock().unwrap().len(),
-         counter.load(std::sync::atomic::Ordering::Relaxed)
-     );
-     let vec2 = vec.lock().unwrap();
-     vec2.clone()*/
-    let els = auto.create_matcher().from(root_window.clone()).find_all();
-    match els {
-        Ok(els) => els
-            .into_iter()
-            .filter(|a| {
-                let incl = must_include(&a);
-                if let Ok(incl) = incl {
-                    incl.0
-                } else if debug {
-                    println!("excluding {} because {:?}", UI2(a.clone()), incl);
-                    false
-                } else {
-                    false
-                }
-            })
-            .collect(),
-        Err(err) => {
-            println!("Error finding els: {:?}", err);
-            vec![]
-        }
+fn add_elements_from_root(
+    root_window: &UIElement,
+    tree: &mut ElementTree,
+    elements: &mut Vec<UIElement>,
+    batches: &mut Batches,
+    budget: &mut Budget,
+    rules: &AppRules,
+) -> Result<()> {
+    let auto = UIAutomation::new()?;
+    let walker = auto.get_control_view_walker()?;
+    //keep what we got so far
+    if let Err(err) = walk(
+        &walker,
+        root_window,
+        None,
+        tree,
+        elements,
+        batches,
+        budget,
+        rules,
+    ) {
+        println!("Error walking els: {:?}", err);
     }
+    Ok(())
 }
-fn get_elements_pid(pid: i32, debug: bool) -> Vec<UIElement> {
-    let auto = UIAutomation::new().unwrap();
+fn get_root_pid(pid: i32) -> Option<UIElement> {
+    let auto = UIAutomation::new().ok()?;
 
     let root_window = auto
         .create_matcher()
         .depth(5)
         .filter_fn(Box::new(move |e: &UIElement| {
-            Ok(e.get_process_id().unwrap() == pid)
+            Ok(e.get_process_id().is_ok_and(|p| p == pid))
         }))
         .find_first();
-    let root_window = if let Ok(win) = root_window {
-        win
+    if let Ok(win) = root_window {
+        Some(win)
     } else {
         println!("no topmost window found");
-        auto.get_root_element().unwrap()
-    };
+        auto.get_root_element().ok()
+    }
+}
+///top level windows on screen apart from ours and the taskbar, the desktop lists them frontmost first
+fn visible_windows() -> Result<Vec<UIElement>> {
+    let auto = UIAutomation::new()?;
+    let walker = auto.get_control_view_walker()?;
+    let root = auto.get_root_element()?;
+    let own = std::process::id() as i32;
+    let mut windows = vec![];
+    let mut next = walker.get_first_child(&root).ok();
+    while let Some(window) = next {
+        //minimized windows are offscreen, and one which closed meanwhile is skipped
+        let visible = !window.is_offscreen().unwrap_or(true)
+            && window.get_process_id().is_ok_and(|p| p != own)
+            && window.get_classname().unwrap_or_default() != "Shell_TrayWnd";
+        next = walker.get_next_sibling(&window).ok();
+        if visible {
+            windows.push(window);
+        }
+    }
+    Ok(windows)
+}
 
-    get_elements_from_root(&root_window, debug)
+///popup menus are top level, frontmost first so an open submenu comes before its menu
+fn open_menu() -> Result<Option<UIElement>> {
+    let auto = UIAutomation::new()?;
+    let walker = auto.get_control_view_walker()?;
+    let root = auto.get_root_element()?;
+    let mut next = walker.get_first_child(&root).ok();
+    while let Some(window) = next {
+        //a window closing meanwhile is no menu
+        let menu = window
+            .get_control_type()
+            .is_ok_and(|c| c == ControlType::Menu)
+            && !window.is_offscreen().unwrap_or(true);
+        if menu {
+            return Ok(Some(window));
+        }
+        next = walker.get_next_sibling(&window).ok();
+    }
+    Ok(None)
 }
-fn get_elements_taskbar() -> Vec<UIElement> {
-    let auto = UIAutomation::new().unwrap();
-    let root_window = auto
-        .create_matcher()
+
+fn get_root_taskbar() -> Option<UIElement> {
+    let auto = UIAutomation::new().ok()?;
+    auto.create_matcher()
         .depth(2)
         .classname("Shell_TrayWnd")
         .find_first()
-        .unwrap();
-
-    get_elements_from_root(&root_window, false)
+        .ok()
 }
 
-impl PartialEq for &UiElement {
-    fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
-            && self.control == other.control
-            && self.x == other.x
-            && self.y == other.y
-            && self.width == other.width
-            && self.height == other.height
+impl ShellSurfaces for Windows {
+    fn add_shell_surfaces(
+        &mut self,
+        batches: &mut Batches,
+    ) -> std::result::Result<(), AccessError> {
+        let start = std::time::Instant::now();
+        let first = self.tree.nodes.len();
+        if let Some(root) = get_root_taskbar() {
+            let pid = root.get_process_id().unwrap_or_default();
+            add_elements_from_root(
+                &root,
+                &mut self.tree,
+                &mut self.elements,
+                batches,
+                &mut self.budget,
+                &self.rules.for_app("explorer", pid, false),
+            )
+            .map_err(access_error("taskbar"))?;
+        }
+        for node in self.tree.nodes[first..].iter_mut() {
+            node.element.parent = "taskbar".into();
+            node.element.window = "taskbar".into();
+        }
+
+        println!(
+            "got {} taskbar elements in {}ms",
+            self.tree.nodes.len() - first,
+            start.elapsed().as_millis()
+        );
+        Ok(())
     }
 }
-impl From<&UIElement> for UiElement {
-    fn from(element: &UIElement) -> Self {
+
+///fails when the element went away while we were walking
+impl TryFrom<&UIElement> for UiElement {
+    type Error = Error;
+
+    fn try_from(element: &UIElement) -> Result<Self> {
         let rect = element.get_bounding_rectangle();
         let rect = if let Ok(re) = rect {
             re
@@ -255,11 +553,22 @@ impl From<&UIElement> for UiElement {
         let y = rect.get_top();
         let width = rect.get_width();
         let height = rect.get_height();
-        let name = element.get_name().unwrap();
-        let control = format!("{:?}", element.get_control_type().unwrap());
-        let pid = element.get_process_id().unwrap();
+        let name = element.get_name()?;
+        let control_type = element.get_control_type()?;
+        let control = format!("{:?}", control_type);
+        let pid = element.get_process_id()?;
+        let id = element
+            .get_runtime_id()
+            .map(|ids| {
+                ids.iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join(".")
+            })
+            .unwrap_or_default();
         // println!("{}:{}:{:?}", name, id, element);
-        UiElement {
+        Ok(UiElement {
+            id,
             name,
             x,
             y,
@@ -270,45 +579,64 @@ impl From<&UIElement> for UiElement {
             x_offset: 0,
             y_offset: 0,
             parent: String::new(),
-        }
+            group: String::new(),
+            editable: control_type == ControlType::Edit,
+            //menu bar items and submenus expand, plain items do not
+            opens_menu: control_type == ControlType::MenuItem
+                && element.get_pattern::<UIExpandCollapsePattern>().is_ok(),
+            window_id: String::new(),
+            window: String::new(),
+            space: CoordSpace::Physical,
+            clip: None,
+            offscreen: false,
+            states: vec![],
+        })
     }
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
-/*fn walk(
+#[allow(clippy::too_many_arguments)]
+fn walk(
     walker: &UITreeWalker,
     element: &UIElement,
-    vec: Arc<Mutex<Vec<UIElement>>>,
-    counter: Arc<AtomicU32>,
-    pid: i32,
-    level: usize,
-    debug: bool,
+    parent: Option<usize>,
+    tree: &mut ElementTree,
+    elements: &mut Vec<UIElement>,
+    batches: &mut Batches,
+    budget: &mut Budget,
+    rules: &AppRules,
 ) -> Result<()> {
-    counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-    if level > 20 {
+    if budget.spent(tree) {
         return Ok(());
     }
-    let mut correct_pid = true;
-    //dont check wrong pid
-    let el_pid = element.get_process_id()?;
-    if el_pid != pid {
-        correct_pid = false;
-        // return Ok(());
-    }
-
-    let incl = must_include(element)?;
-    if incl.0 && correct_pid {
-        vec.lock().unwrap().push(element.clone());
-    } else if debug {
-        if !correct_pid {
-            println!("excluding {} because wrong pid", UI2(element.clone()));
-        } else {
-            println!("excluding {} because {}", UI2(element.clone()), incl.1);
+    let mut info = match UiElement::try_from(element) {
+        Ok(info) => info,
+        Err(e) => {
+            if rules.debug {
+                println!("skipping an element which went away: {e}");
+            }
+            return Ok(());
         }
-        vec.lock().unwrap().push(element.clone());
+    };
+    info.offscreen = element.is_offscreen().unwrap_or(true);
+    if rules.wants_states() {
+        info.states = states(element);
     }
+    let include = match must_include(element, &info, rules) {
+        Ok(()) => true,
+        Err(reason) => {
+            if rules.debug {
+                println!("excluding {} because {reason}", UI2(element.clone()));
+            }
+            false
+        }
+    };
+    let id = tree.add(parent, info, include);
+    elements.push(element.clone());
+    batches.walked(tree);
 
-    if !must_descend(element)? && !debug {
+    let has_children = || walker.get_first_child(element).is_ok();
+    if !budget.descend(&tree.nodes[id], has_children) || !rules.descend(&tree.nodes[id].element) {
         return Ok(());
     }
 
@@ -316,51 +644,88 @@ pub type Result<T> = core::result::Result<T, Error>;
         walk(
             walker,
             &child,
-            vec.clone(),
-            counter.clone(),
-            pid,
-            level + 1,
-            debug,
+            Some(id),
+            tree,
+            elements,
+            batches,
+            budget,
+            rules,
         )?;
 
         let mut next = child;
-        while let Ok(sibling) = walker.get_next_sibling(&next) {
+        //stop asking for siblings once spent, there can be thousands
+        while !budget.spent(tree) {
+            let Ok(sibling) = walker.get_next_sibling(&next) else {
+                break;
+            };
             walk(
                 walker,
                 &sibling,
-                vec.clone(),
-                counter.clone(),
-                pid,
-                level + 1,
-                debug,
+                Some(id),
+                tree,
+                elements,
+                batches,
+                budget,
+                rules,
             )?;
 
             next = sibling;
         }
     }
-    /* println!(
-        "{},{},{:?},\"{}\"",
-        start.elapsed().as_millis(),
-        level,
-        element.get_control_type().unwrap(),
-        element.get_name().unwrap_or_default(),
-    );*/
     Ok(())
-}*/
+}
+
+///the states rules can match on, each is a call to the application
+fn states(element: &UIElement) -> Vec<String> {
+    let toggled = element
+        .get_pattern::<UITogglePattern>()
+        .and_then(|p| p.get_toggle_state());
+    let expanded = element
+        .get_pattern::<UIExpandCollapsePattern>()
+        .and_then(|p| p.get_state());
+    [
+        ("enabled", element.is_enabled().unwrap_or(false)),
+        (
+            "focusable",
+            element.is_keyboard_focusable().unwrap_or(false),
+        ),
+        ("focused", element.has_keyboard_focus().unwrap_or(false)),
+        (
+            "selected",
+            element
+                .get_pattern::<UISelectionItemPattern>()
+                .and_then(|p| p.is_selected())
+                .unwrap_or(false),
+        ),
+        ("checked", toggled.is_ok_and(|t| t == ToggleState::On)),
+        (
+            "expanded",
+            expanded.is_ok_and(|e| e == ExpandCollapseState::Expanded),
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, has)| *has)
+    .map(|(name, _)| name.to_string())
+    .collect()
+}
 
 struct UI2(UIElement);
 impl Display for UI2 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let rect = self.0.get_bounding_rectangle().unwrap();
+        let rect = self.0.get_bounding_rectangle().unwrap_or_default();
 
         let x = rect.get_left();
         let y = rect.get_top();
         let width = rect.get_width();
         let height = rect.get_height();
-        let typ = format!("{:?}", self.0.get_control_type().unwrap());
+        let typ = self
+            .0
+            .get_control_type()
+            .map(|t| format!("{t:?}"))
+            .unwrap_or_default();
         let classname = self.0.get_classname().unwrap_or_default();
-        let name = self.0.get_name().unwrap();
-        let pid = self.0.get_process_id().unwrap();
+        let name = self.0.get_name().unwrap_or_default();
+        let pid = self.0.get_process_id().unwrap_or_default();
 
         write!(
             f,
@@ -370,71 +735,101 @@ impl Display for UI2 {
     }
 }
 
-fn must_include(element: &UIElement) -> Result<(bool, String)> {
-    // return Ok(true);
-    if element.is_offscreen()? {
-        //  println!("Excluding offscreen element {:?}", element);
-        return Ok((false, "Offscreen".into()));
+fn must_include(
+    element: &UIElement,
+    info: &UiElement,
+    rules: &AppRules,
+) -> std::result::Result<(), String> {
+    //items scrolled out of a list can be scrolled to, anything else offscreen is hidden
+    if info.offscreen && !(rules.offscreen && element.get_pattern::<UIScrollItemPattern>().is_ok())
+    {
+        return Err("Offscreen".into());
     }
+    rules.include(info)
+}
 
-    //return Ok(true);
-
-    let ctype = match element.get_control_type()? {
-        ControlType::Button
-        | ControlType::ListItem
-        | ControlType::TreeItem
-        | ControlType::Hyperlink
-        | ControlType::ComboBox
-        | ControlType::RadioButton
-        | ControlType::CheckBox
-        | ControlType::TabItem => true,
-
-        ControlType::Text
-        | ControlType::ScrollBar
-        | ControlType::Menu
-        | ControlType::MenuItem
-        | ControlType::Edit
-        | ControlType::Calendar
-        | ControlType::Image
-        | ControlType::List
-        | ControlType::MenuBar
-        | ControlType::ProgressBar
-        | ControlType::Slider
-        | ControlType::Spinner
-        | ControlType::StatusBar
-        | ControlType::Tab
-        | ControlType::ToolBar
-        | ControlType::ToolTip
-        | ControlType::Tree
-        | ControlType::Custom
-        | ControlType::Group
-        | ControlType::Thumb
-        | ControlType::DataGrid
-        | ControlType::DataItem
-        | ControlType::Document
-        | ControlType::SplitButton
-        | ControlType::Window
-        | ControlType::Pane
-        | ControlType::Header
-        | ControlType::HeaderItem
-        | ControlType::Table
-        | ControlType::TitleBar
-        | ControlType::Separator
-        | ControlType::SemanticZoom
-        | ControlType::AppBar => false,
-    };
+///register the handler for the whole desktop, uia calls it on its own threads for as long as
+///the returned automation is kept
+fn watch(handler: ChangeHandler) -> Result<UIAutomation> {
+    let automation = UIAutomation::new()?;
+    let root = automation.get_root_element()?;
+    let (uia, root): (&IUIAutomation, &IUIAutomationElement) = (automation.as_ref(), root.as_ref());
+    let structure: IUIAutomationStructureChangedEventHandler = handler.clone().into();
+    let property: IUIAutomationPropertyChangedEventHandler = handler.into();
+    unsafe {
+        uia.AddStructureChangedEventHandler(
+            root,
+            TreeScope_Subtree,
+            None::<&IUIAutomationCacheRequest>,
+            &structure,
+        )?;
+        uia.AddPropertyChangedEventHandlerNativeArray(
+            root,
+            TreeScope_Subtree,
+            None::<&IUIAutomationCacheRequest>,
+            &property,
+            CHANGE_PROPERTIES,
+        )?;
+    }
+    Ok(automation)
+}
 
-    if ctype {
-        Ok((true, String::new()))
-    } else {
-        Ok((false, "unallowed control".to_string()))
+///adds the pid of the element which changed to the changes
+#[implement(
+    IUIAutomationStructureChangedEventHandler,
+    IUIAutomationPropertyChangedEventHandler
+)]
+#[derive(Clone)]
+struct ChangeHandler {
+    changes: Changes,
+    watched: Arc<Mutex<Option<i32>>>,
+    own: i32,
+}
+
+impl ChangeHandler {
+    fn changed(&self, sender: Option<&IUIAutomationElement>) {
+        let Some(pid) = sender.and_then(|s| unsafe { s.CurrentProcessId() }.ok()) else {
+            return;
+        };
+        //the overlay changes whenever it shows
+        let watched = *self.watched.lock().unwrap();
+        if pid != self.own && watched.is_none_or(|w| w == pid) {
+            self.changes.lock().unwrap().insert(pid);
+        }
     }
 }
-/*
-fn must_descend(element: &UIElement) -> Result<bool> {
-    Ok(match element.get_control_type()? {
-        ControlType::DataGrid => false, //or get lots in excel
-        _ => true,
-    })
+
+impl IUIAutomationStructureChangedEventHandler_Impl for ChangeHandler {
+    fn HandleStructureChangedEvent(
+        &self,
+        sender: Option<&IUIAutomationElement>,
+        _change: StructureChangeType,
+        _runtime_id: *const SAFEARRAY,
+    ) -> ::windows::core::Result<()> {
+        self.changed(sender);
+        Ok(())
+    }
+}
+
+impl IUIAutomationPropertyChangedEventHandler_Impl for ChangeHandler {
+    fn HandlePropertyChangedEvent(
+        &self,
+        sender: Option<&IUIAutomationElement>,
+        _property: UIA_PROPERTY_ID,
+        _value: &VARIANT,
+    ) -> ::windows::core::Result<()> {
+        self.changed(sender);
+        Ok(())
+    }
+}
+
+///executable names by pid, as windows only know their process
+fn process_names() -> HashMap<i32, String> {
+    let system =
+        System::new_with_specifics(RefreshKind::new().with_processes(ProcessRefreshKind::new()));
+    system
+        .processes()
+        .iter()
+        .map(|(pid, p)| (pid.as_u32() as i32, p.name().to_string()))
+        .collect()
 }
-*/
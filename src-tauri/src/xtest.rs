@@ -0,0 +1,108 @@
+use std::error::Error;
+
+use x11rb::{
+    connection::{Connection, RequestConnection},
+    protocol::{
+        xproto::{
+            ConnectionExt as _, Keycode, Keysym, Window, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT,
+            KEY_PRESS_EVENT, KEY_RELEASE_EVENT, MOTION_NOTIFY_EVENT,
+        },
+        xtest::{ConnectionExt, X11_EXTENSION_NAME},
+    },
+    rust_connection::RustConnection,
+    CURRENT_TIME,
+};
+
+use crate::traits::{AccessError, InputCalls, Modifier, MouseButton};
+
+const XK_SHIFT_L: Keysym = 0xffe1;
+const XK_CONTROL_L: Keysym = 0xffe3;
+
+///pointer injection on X11 through the XTest extension
+pub struct XTest {
+    conn: RustConnection,
+    root: Window,
+    shift: Option<Keycode>,
+    control: Option<Keycode>,
+}
+
+impl XTest {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let (conn, screen) = x11rb::connect(None)?;
+        if conn.extension_information(X11_EXTENSION_NAME)?.is_none() {
+            return Err("XTest extension not available".into());
+        }
+        let root = conn.setup().roots[screen].root;
+        let shift = keycode(&conn, XK_SHIFT_L);
+        let control = keycode(&conn, XK_CONTROL_L);
+        Ok(XTest {
+            conn,
+            root,
+            shift,
+            control,
+        })
+    }
+
+    fn fake_input(&self, typ: u8, detail: u8, x: i32, y: i32) -> Result<(), AccessError> {
+        self.conn
+            .xtest_fake_input(typ, detail, CURRENT_TIME, self.root, x as i16, y as i16, 0)
+            .and_then(|_| self.conn.flush())
+            .map_err(|e| AccessError::InjectionFailed(e.to_string()))
+    }
+}
+
+impl InputCalls for XTest {
+    fn move_to(&self, x: i32, y: i32) -> Result<(), AccessError> {
+        //detail 0 means absolute coordinates
+        self.fake_input(MOTION_NOTIFY_EVENT, 0, x, y)
+    }
+
+    fn button(&self, button: MouseButton, down: bool) -> Result<(), AccessError> {
+        let detail = match button {
+            MouseButton::Left => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Right => 3,
+        };
+        let typ = if down {
+            BUTTON_PRESS_EVENT
+        } else {
+            BUTTON_RELEASE_EVENT
+        };
+        self.fake_input(typ, detail, 0, 0)
+    }
+
+    fn key(&self, key: Modifier, down: bool) -> Result<(), AccessError> {
+        let code = match key {
+            Modifier::Shift => self.shift,
+            Modifier::Control => self.control,
+        };
+        let Some(code) = code else {
+            return Err(AccessError::InjectionFailed(format!(
+                "no keycode for {key:?}"
+            )));
+        };
+        let typ = if down {
+            KEY_PRESS_EVENT
+        } else {
+            KEY_RELEASE_EVENT
+        };
+        self.fake_input(typ, code, 0, 0)
+    }
+}
+
+///find the keycode that produces a keysym in the current keyboard mapping
+fn keycode(conn: &RustConnection, keysym: Keysym) -> Option<Keycode> {
+    let setup = conn.setup();
+    let (min, max) = (setup.min_keycode, setup.max_keycode);
+    let mapping = conn
+        .get_keyboard_mapping(min, max - min + 1)
+        .ok()?
+        .reply()
+        .ok()?;
+    let per = mapping.keysyms_per_keycode as usize;
+    let index = mapping
+        .keysyms
+        .chunks(per)
+        .position(|k| k.contains(&keysym))?;
+    Some(min + index as u8)
+}
@@ -9,6 +9,39 @@ register('Alt+Enter', () => {
   invoke("show");
 })
 
+type ErrorEvent = {
+  kind: string;
+  message: string;
+}
+
+type Prerequisite = {
+  id: string;
+  description: string;
+  ok: boolean;
+  fixable: boolean;
+  hint: string;
+}
+
+type StatusEvent = {
+  ok: boolean;
+  prerequisites: Prerequisite[];
+}
+
+type CompleteEvent = {
+  hinted: number;
+  walked: number;
+  millis: number;
+  //which walk budget ran out, if any
+  truncated: "Depth" | "Elements" | "Time" | null;
+}
+
+//hints which changed while the overlay is open, by label
+type HintsChanged = {
+  added: Hint[];
+  removed: string[];
+  moved: Hint[];
+}
+
 type Hint = {
   text: string;
   x: number;
@@ -20,6 +53,12 @@ type Hint = {
   hint: string;
   control: string;
   parent: string;
+  group: string;
+  editable: boolean;
+  window: string;
+  space: "Physical" | "Logical";
+  //scrolled out of view, so only listed
+  offscreen: boolean;
 }
 
 function App() {
@@ -29,6 +68,11 @@ function App() {
   const [spaceDown, setSpaceDown] = useState(false);
   const [pressedNav, setPressedNav] = useState(false);
   const [finding, setFinding] = useState(true);
+  const [truncated, setTruncated] = useState(null as CompleteEvent["truncated"]);
+  //the editable element we are entering text for
+  const [typing, setTyping] = useState(null as Hint | null);
+  const [error, setError] = useState(null as ErrorEvent | null);
+  const [missing, setMissing] = useState([] as Prerequisite[]);
 
   const inputArea = useRef<HTMLDivElement>(null);
   const inputBox = useRef<HTMLInputElement>(null);
@@ -36,8 +80,13 @@ function App() {
   async function invoke_hide_and_clear() {
     console.log("invoking hide");
     await invoke("hide");
+    clear();
+  }
+
+  function clear() {
     setResults([]);
     setInput("");
+    setTyping(null);
   }
 
   useEffect(() => {
@@ -57,17 +106,44 @@ function App() {
     setResults(event.payload as Hint[]);
     setSelectedIndex(old => Math.min(old, results.length - 1));
     setSelectedIndex(old => Math.max(old, 0));
-    setFinding(false);
+  });
 
+  listen("hints_changed", (event) => {
+    const changes = event.payload as HintsChanged;
+    setResults(old => old
+      .filter(h => !changes.removed.includes(h.hint))
+      .map(h => changes.moved.find(m => m.hint === h.hint) ?? h)
+      .concat(changes.added));
+    setSelectedIndex(old => Math.max(Math.min(old, results.length - 1), 0));
+  });
+
+  //hints come in as they are found, this is once all have been
+  listen("complete", (event) => {
+    setFinding(false);
+    setTruncated((event.payload as CompleteEvent).truncated);
   });
 
   listen("show", (_) => {
     setFinding(true);
+    setTruncated(null);
+    setError(null);
+  });
+
+  listen("status", (event) => {
+    const status = event.payload as StatusEvent;
+    setMissing(status.prerequisites.filter(p => !p.ok));
+  });
+
+  listen("error", (event) => {
+    setError(event.payload as ErrorEvent);
+    setFinding(false);
   });
 
   async function update_input(newValue: string) {
     setInput(_ => newValue);
-    setFinding(true);
+    if (typing) {
+      return;
+    }
     await invoke("update_input", { input: newValue });
   }
 
@@ -75,23 +151,44 @@ function App() {
 
     var hint = results[selectedIndex].hint;
 
-    await invoke_hide_and_clear();
+    //choice hides the window itself, hide first would drop the hints it is chosen from
+    try {
+      await invoke("choice", { choice: hint, action: action });
+    } catch (e) {
+      console.log("choice failed: " + e);
+    }
+    clear();
+  }
 
-    switch (action) {
-      case "LeftClick":
-        await invoke("choice", { choice: hint, action: "LeftClick" });
-        break;
-      case "RightClick":
-        await invoke("choice", { choice: hint, action: "RightClick" });
-        break;
 
+  async function type_text(hint: Hint, text: string) {
+    try {
+      await invoke("choice", { choice: hint.hint, action: "Type", text: text });
+    } catch (e) {
+      console.log("typing failed: " + e);
     }
+    clear();
   }
 
-
   async function input_keydown(e: React.KeyboardEvent<HTMLInputElement>) {
     console.log("down:" + e.key);
-    if (e.key === " ") {
+    if (typing) {
+      if (e.key === "Enter") {
+        type_text(typing, input);
+      } else if (e.key === "Escape") {
+        e.preventDefault();
+        //back to the hints
+        setTyping(null);
+        setInput("");
+        await invoke("update_input", { input: "" });
+      }
+      return;
+    }
+    if (e.key === "Tab" && results[selectedIndex]?.editable) {
+      e.preventDefault();
+      setTyping(results[selectedIndex]);
+      setInput("");
+    } else if (e.key === " ") {
       e.preventDefault();
       setSpaceDown(true);
     } else if ((e.key.toUpperCase() === "J" && spaceDown) || e.key == "ArrowDown") {
@@ -113,15 +210,31 @@ function App() {
       }
     } else if (e.key === "Escape") {
       await invoke_hide_and_clear();
+    } else if (e.key === "Enter" && e.ctrlKey && e.shiftKey) {
+      invoke_choice("MiddleClick");
     } else if (e.key === "Enter" && e.ctrlKey) {
       invoke_choice("RightClick");
+    } else if (e.key === "Enter" && e.shiftKey) {
+      invoke_choice("DoubleClick");
     } else if (e.key === "Enter") {
       invoke_choice("LeftClick");
+    } else if (e.altKey && e.code === "KeyH") {
+      e.preventDefault();
+      invoke_choice("Hover");
+    } else if (e.altKey && e.code === "KeyF") {
+      e.preventDefault();
+      invoke_choice("Focus");
+    } else if (e.altKey && e.code === "KeyS") {
+      e.preventDefault();
+      invoke_choice("ShiftClick");
+    } else if (e.altKey && e.code === "KeyC") {
+      e.preventDefault();
+      invoke_choice("CtrlClick");
     }
 
   }
   async function input_keyup(e: React.KeyboardEvent<HTMLInputElement>) {
-    if (e.key === " ") {
+    if (e.key === " " && !typing) {
       if (pressedNav) {
         e.preventDefault();
       } else {
@@ -143,25 +256,37 @@ function App() {
           autoCapitalize="off"
           onChange={(e) => update_input(e.currentTarget.value)}
           value={input}
-          placeholder="Search for element names or hint shortcut"
+          placeholder={typing ? "Text to type into " + (typing.text || typing.control) : "Search for element names or hint shortcut"}
           onKeyDown={(e) => input_keydown(e)}
           onKeyUp={(e) => input_keyup(e)}
           onBlur={() => inputBox.current?.focus()}
           ref={inputBox}
         />
-        <label className="input-label">Press <a className="highlight">Enter</a> to left click, <a className="highlight">Ctrl+Enter</a> to right click. Hold <a className="highlight">Space+J/K</a> or <a className="highlight">Down/Up</a> to scroll.<span style={{ marginLeft: '10px' }}>{finding ? <div className="loader"></div> : "Found " + results.length}</span></label>
+        <label className="input-label">Press <a className="highlight">Enter</a> to left click, <a className="highlight">Ctrl+Enter</a> to right click, <a className="highlight">Shift+Enter</a> to double click, <a className="highlight">Tab</a> to type into a text box. Hold <a className="highlight">Space+J/K</a> or <a className="highlight">Down/Up</a> to scroll.<span style={{ marginLeft: '10px' }}>{finding && <div className="loader"></div>}{"Found " + results.length}{truncated && " (stopped early, " + truncated.toLowerCase() + " limit reached)"}</span></label>
+
+        {error && <div className="error">{error.message}</div>}
+
+        {missing.map((p) =>
+          <div className="error">
+            Not ready: {p.description}. {p.hint}
+            {p.fixable && <button className="enable" onClick={() => invoke("enable_prerequisite", { id: p.id })}>Enable</button>}
+          </div>
+        )}
 
         {results.length > 0 &&
           <div className="holder">
             {results.map((result, i) => {
 
               return (
-                <div className={i === selectedIndex ? "result result-selected" : "result"}><div className="result-left">{result.text} ({result.hint})</div><div className="result-right">{result.parent} | {result.control}</div></div>
+                <div className={i === selectedIndex ? "result result-selected" : "result"}><div className="result-left">{result.text} ({result.hint})</div><div className="result-right">{result.window || result.parent}{result.group && result.group !== result.parent ? " > " + result.group : ""} | {result.control}{result.offscreen && " | out of view"}</div></div>
               );
             })}
           </div>}
       </div>
       {results.map((result, i) => {
+        if (result.offscreen) {
+          return null;
+        }
         let left = result.x + result.x_offset;
         let top = result.y + result.y_offset;
         let wid = result.width;